@@ -53,20 +53,41 @@ impl<F: config::Format + Send + Sync + Debug> AsyncSource for HttpSource<F> {
     }
 }
 
+/// Watches for the life of the process, see `config_hot_reload_with` to stop it.
 pub fn config_hot_reload<T: for<'a> Deserialize<'a> + Sync + Send + 'static>(
     config: Arc<RwLock<T>>,
     config_path: String,
 ) -> Result<()> {
+    let watcher = config_hot_reload_with(config, config_path, |_| {})?;
+    std::mem::forget(watcher);
+    Ok(())
+}
+
+/// Like `config_hot_reload`, calling `on_reload` with each newly loaded config,
+/// e.g. to apply `LogConfig` through `LogHandle::apply`.
+/// Watching stops when the returned watcher is dropped.
+pub fn config_hot_reload_with<T, F>(
+    config: Arc<RwLock<T>>,
+    config_path: String,
+    on_reload: F,
+) -> Result<RecommendedWatcher>
+where
+    T: for<'a> Deserialize<'a> + Sync + Send + 'static,
+    F: Fn(&T) + Send + 'static,
+{
     let config_path_clone = config_path.clone();
-    // reload config
     let mut watcher = RecommendedWatcher::new(
         move |result: Result<Event, notify::Error>| {
-            let event = result.unwrap();
+            let event = match result {
+                Ok(event) => event,
+                Err(error) => return error!("Error watching config: {:?}", error),
+            };
 
             if event.kind.is_modify() {
                 match file_config(&config_path_clone) {
                     Ok(new_config) => {
                         info!("reloading config");
+                        on_reload(&new_config);
                         *config.write() = new_config;
                     }
                     Err(error) => error!("Error reloading config: {:?}", error),
@@ -76,5 +97,43 @@ pub fn config_hot_reload<T: for<'a> Deserialize<'a> + Sync + Send + 'static>(
         notify::Config::default(),
     )?;
     watcher.watch(Path::new(&config_path), RecursiveMode::Recursive)?;
+    Ok(watcher)
+}
+
+#[cfg(feature = "log")]
+#[test]
+fn test() -> Result<()> {
+    use crate::log::{LogConfig, scoped_log};
+
+    let dir = std::env::temp_dir().join("common_x_config_reload_test");
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("log.toml");
+    std::fs::write(&path, "filter = \"info\"\n")?;
+
+    let config: LogConfig = file_config(&path.to_string_lossy())?;
+    let (handle, _guard) = scoped_log(&config)?;
+    let config = Arc::new(RwLock::new(config));
+    let apply = handle.clone();
+    let _watcher = config_hot_reload_with(
+        config.clone(),
+        path.to_string_lossy().into_owned(),
+        move |config: &LogConfig| {
+            if let Err(e) = apply.apply(config) {
+                error!("apply log config failed: {e}");
+            }
+        },
+    )?;
+    std::fs::write(&path, "filter = \"debug\"\n")?;
+
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    while (handle.filter()? != "debug" || config.read().filter != "debug")
+        && std::time::Instant::now() < deadline
+    {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert_eq!(handle.filter()?, "debug");
+    assert_eq!(config.read().filter, "debug");
+    std::fs::remove_dir_all(&dir)?;
     Ok(())
 }
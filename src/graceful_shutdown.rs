@@ -51,6 +51,16 @@ impl CloseToken {
         self.close_rv.recv_async().await.ok();
     }
 
+    pub fn is_closed(&self) -> bool {
+        self.close_rv.is_disconnected()
+    }
+
+    pub fn signal(&self) -> CloseSignal {
+        CloseSignal {
+            close_rv: self.close_rv.clone(),
+        }
+    }
+
     pub fn child_token(&self) -> CloseToken {
         let mut chain = self.close_chain.lock().unwrap();
         let deep = self.deep + 1;
@@ -70,6 +80,27 @@ impl CloseToken {
     }
 }
 
+/// Observes the close of a `CloseToken` without taking part in its shutdown chain,
+/// so dropping it never blocks.
+#[derive(Clone, Debug)]
+pub struct CloseSignal {
+    close_rv: Receiver<()>,
+}
+
+impl CloseSignal {
+    pub fn is_closed(&self) -> bool {
+        self.close_rv.is_disconnected()
+    }
+
+    pub fn closed(&self) {
+        self.close_rv.recv().ok();
+    }
+
+    pub async fn closed_async(&self) {
+        self.close_rv.recv_async().await.ok();
+    }
+}
+
 impl Drop for CloseToken {
     fn drop(&mut self) {
         self.closed();
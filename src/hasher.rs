@@ -1,13 +1,344 @@
-use blake3::Hasher;
+use std::{fmt, io::Read, path::Path, str::FromStr};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use color_eyre::{Report, Result, eyre::eyre};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const BUF_SIZE: usize = 64 * 1024;
+
+/// BLAKE3 output, compared in constant time.
+///
+/// Displayed as hex. Parsing accepts hex, padded standard base64 or base58.
+/// Serialized as a hex string for human-readable formats and as bytes otherwise;
+/// use `#[serde(with = "as_bytes")]` to force bytes.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Digest(blake3::Hash);
+
+impl Digest {
+    pub const fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(blake3::Hash::from_bytes(bytes))
+    }
+
+    pub const fn as_bytes(&self) -> &[u8; 32] {
+        self.0.as_bytes()
+    }
+
+    pub fn to_hex(&self) -> String {
+        self.0.to_hex().to_string()
+    }
+
+    pub fn to_base64(&self) -> String {
+        STANDARD.encode(self.as_bytes())
+    }
+
+    pub fn to_base58(&self) -> String {
+        bs58::encode(self.as_bytes()).into_string()
+    }
+
+    pub fn from_hex(s: &str) -> Result<Self> {
+        Ok(Self(blake3::Hash::from_hex(s)?))
+    }
+
+    pub fn from_base64(s: &str) -> Result<Self> {
+        Self::from_slice(&STANDARD.decode(s)?)
+    }
+
+    pub fn from_base58(s: &str) -> Result<Self> {
+        Self::from_slice(&bs58::decode(s).into_vec()?)
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> Result<Self> {
+        let bytes: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| eyre!("invalid digest length: {}", bytes.len()))?;
+        Ok(Self::from_bytes(bytes))
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Debug for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Digest({self})")
+    }
+}
+
+impl FromStr for Digest {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.len() == 64 {
+            Self::from_hex(s)
+        } else if s.ends_with('=') {
+            Self::from_base64(s)
+        } else {
+            Self::from_base58(s)
+        }
+    }
+}
+
+impl Serialize for Digest {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            serializer.serialize_bytes(self.as_bytes())
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Digest {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct DigestVisitor;
+
+        impl<'de> de::Visitor<'de> for DigestVisitor {
+            type Value = Digest;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a hex, base64 or base58 string or 32 bytes")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Digest, E> {
+                v.parse().map_err(E::custom)
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Digest, E> {
+                Digest::from_slice(v).map_err(E::custom)
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Digest, A::Error> {
+                let mut bytes = [0u8; 32];
+                for (i, b) in bytes.iter_mut().enumerate() {
+                    *b = seq
+                        .next_element()?
+                        .ok_or_else(|| de::Error::invalid_length(i, &self))?;
+                }
+                if seq.next_element::<u8>()?.is_some() {
+                    return Err(de::Error::invalid_length(33, &self));
+                }
+                Ok(Digest::from_bytes(bytes))
+            }
+        }
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(DigestVisitor)
+        } else {
+            deserializer.deserialize_bytes(DigestVisitor)
+        }
+    }
+}
+
+/// Serializes a `Digest` as raw bytes regardless of the format.
+pub mod as_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::Digest;
+
+    pub fn serialize<S: Serializer>(digest: &Digest, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(digest.as_bytes())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Digest, D::Error> {
+        Digest::deserialize(deserializer)
+    }
+}
+
+impl From<blake3::Hash> for Digest {
+    fn from(hash: blake3::Hash) -> Self {
+        Self(hash)
+    }
+}
+
+impl From<Digest> for [u8; 32] {
+    fn from(digest: Digest) -> Self {
+        *digest.as_bytes()
+    }
+}
+
+/// 32-byte secret for keyed hashing, either supplied or produced by `derive_key`.
+///
+/// Compared in constant time.
+#[derive(Clone)]
+pub struct Key([u8; 32]);
+
+impl Key {
+    pub const fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    pub const fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Self) -> bool {
+        blake3::Hash::from_bytes(self.0) == blake3::Hash::from_bytes(other.0)
+    }
+}
+
+impl Eq for Key {}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Key(***)")
+    }
+}
+
+/// Incremental hasher, plain, keyed or in key derivation mode.
+#[derive(Clone, Debug, Default)]
+pub struct Hasher(blake3::Hasher);
+
+impl Hasher {
+    pub fn new() -> Self {
+        Self(blake3::Hasher::new())
+    }
+
+    pub fn new_keyed(key: &Key) -> Self {
+        Self(blake3::Hasher::new_keyed(key.as_bytes()))
+    }
+
+    pub fn new_derive_key(context: &str) -> Self {
+        Self(blake3::Hasher::new_derive_key(context))
+    }
+
+    pub fn update(&mut self, data: &[u8]) -> &mut Self {
+        self.0.update(data);
+        self
+    }
+
+    pub fn update_reader(&mut self, reader: impl Read) -> Result<&mut Self> {
+        self.0.update_reader(reader)?;
+        Ok(self)
+    }
+
+    pub async fn update_async_reader(
+        &mut self,
+        mut reader: impl AsyncRead + Unpin,
+    ) -> Result<&mut Self> {
+        let mut buf = vec![0; BUF_SIZE];
+        loop {
+            let n = reader.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            self.0.update(&buf[..n]);
+        }
+        Ok(self)
+    }
+
+    /// Memory-maps the file, falling back to buffered reads for small files.
+    pub fn update_file(&mut self, path: impl AsRef<Path>) -> Result<&mut Self> {
+        self.0.update_mmap(path)?;
+        Ok(self)
+    }
+
+    /// Like `update_file`, hashing the mapped file on the rayon thread pool.
+    pub fn update_file_rayon(&mut self, path: impl AsRef<Path>) -> Result<&mut Self> {
+        self.0.update_mmap_rayon(path)?;
+        Ok(self)
+    }
+
+    pub fn finalize(&self) -> Digest {
+        self.0.finalize().into()
+    }
+}
+
+pub fn digest(data: &[u8]) -> Digest {
+    blake3::hash(data).into()
+}
 
 pub fn hash(data: &[u8]) -> [u8; 32] {
-    let mut hasher = Hasher::new();
-    hasher.update(data);
-    *hasher.finalize().as_bytes()
+    digest(data).into()
 }
 
 pub fn hash_hex(data: &[u8]) -> String {
-    let mut hasher = Hasher::new();
-    hasher.update(data);
-    hasher.finalize().to_hex().to_string()
+    digest(data).to_string()
+}
+
+pub fn hash_reader(reader: impl Read) -> Result<Digest> {
+    Ok(Hasher::new().update_reader(reader)?.finalize())
+}
+
+pub async fn hash_async_reader(reader: impl AsyncRead + Unpin) -> Result<Digest> {
+    Ok(Hasher::new().update_async_reader(reader).await?.finalize())
+}
+
+pub fn hash_file(path: impl AsRef<Path>) -> Result<Digest> {
+    Ok(Hasher::new().update_file(path)?.finalize())
+}
+
+/// Multithreaded `hash_file`, only worth it for files of a few MB and up.
+pub fn hash_file_rayon(path: impl AsRef<Path>) -> Result<Digest> {
+    Ok(Hasher::new().update_file_rayon(path)?.finalize())
+}
+
+pub fn keyed_hash(key: &Key, data: &[u8]) -> Digest {
+    blake3::keyed_hash(key.as_bytes(), data).into()
+}
+
+pub fn verify_keyed_hash(key: &Key, data: &[u8], mac: &Digest) -> bool {
+    keyed_hash(key, data) == *mac
+}
+
+/// `context` should be hardcoded, globally unique and application-specific,
+/// e.g. `"common_x 2025-01-01 tenant secret"`.
+pub fn derive_key(context: &str, key_material: &[u8]) -> Key {
+    Key(blake3::derive_key(context, key_material))
+}
+
+#[tokio::test]
+async fn test() -> Result<()> {
+    let data = vec![7u8; 3 * BUF_SIZE + 5];
+    let digest = Digest::from_bytes(hash(&data));
+    assert_eq!(digest.to_hex(), hash_hex(&data));
+    assert_eq!(hash_reader(data.as_slice())?, digest);
+    assert_eq!(hash_async_reader(data.as_slice()).await?, digest);
+
+    let path = std::env::temp_dir().join("common_x_hasher_test");
+    std::fs::write(&path, &data)?;
+    assert_eq!(hash_file(&path)?, digest);
+    assert_eq!(hash_file_rayon(&path)?, digest);
+    std::fs::remove_file(&path)?;
+
+    let key = derive_key("common_x hasher test", b"tenant-1");
+    assert_eq!(key, derive_key("common_x hasher test", b"tenant-1"));
+    assert_ne!(key, derive_key("common_x hasher test", b"tenant-2"));
+    let mac = keyed_hash(&key, b"payload");
+    assert!(verify_keyed_hash(&key, b"payload", &mac));
+    assert!(!verify_keyed_hash(&key, b"payload!", &mac));
+    Ok(())
+}
+
+#[test]
+fn test_digest() -> Result<()> {
+    #[derive(Serialize, Deserialize)]
+    struct Asset {
+        digest: Digest,
+        #[serde(with = "as_bytes")]
+        raw: Digest,
+    }
+
+    let d = digest(b"common_x");
+    assert_eq!(d.to_string().parse::<Digest>()?, d);
+    assert_eq!(d.to_base64().parse::<Digest>()?, d);
+    assert_eq!(d.to_base58().parse::<Digest>()?, d);
+    assert!("00".parse::<Digest>().is_err());
+
+    let asset = Asset { digest: d, raw: d };
+    let json = serde_json::to_string(&asset)?;
+    assert!(json.contains(&hash_hex(b"common_x")));
+    let de: Asset = serde_json::from_str(&json)?;
+    assert_eq!((de.digest, de.raw), (d, d));
+    let ron = ron::to_string(&asset)?;
+    let de: Asset = ron::from_str(&ron)?;
+    assert_eq!((de.digest, de.raw), (d, d));
+    let de: Digest = serde_json::from_str(&format!("{:?}", d.to_base58()))?;
+    assert_eq!(de, d);
+    Ok(())
 }
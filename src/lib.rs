@@ -10,6 +10,10 @@ pub mod hasher;
 pub mod log;
 #[cfg(feature = "mailer")]
 pub mod mailer;
+#[cfg(feature = "manifest")]
+pub mod manifest;
+#[cfg(feature = "merkle")]
+pub mod merkle;
 #[cfg(feature = "rand")]
 pub mod rand;
 #[cfg(feature = "restful")]
@@ -18,6 +22,10 @@ pub mod restful;
 pub mod ron;
 #[cfg(feature = "signal")]
 pub mod signal;
+#[cfg(feature = "supervisor")]
+pub mod supervisor;
+#[cfg(test)]
+mod test_util;
 #[cfg(feature = "time")]
 pub mod time;
 #[cfg(feature = "tls")]
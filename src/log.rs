@@ -1,14 +1,125 @@
+mod capture;
+mod format;
+mod hooks;
+mod otel;
+mod query;
+mod rate_limit;
+mod redact;
+mod rolling;
+mod time;
+
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    sync::{Arc, Mutex, OnceLock, Weak},
+};
+
+use color_eyre::{Result, eyre::ensure};
 use serde::{Deserialize, Serialize};
+use tracing::{Dispatch, info, subscriber::DefaultGuard};
+use tracing_appender::non_blocking::{NonBlockingBuilder, WorkerGuard};
 use tracing_subscriber::{
-    EnvFilter,
-    fmt::{format::Writer, time::FormatTime, writer::MakeWriterExt},
+    EnvFilter, Layer, Registry,
+    fmt::{
+        MakeWriter,
+        format::{DefaultFields, PrettyFields},
+        writer::BoxMakeWriter,
+    },
+    layer::{Layered, SubscriberExt},
+    reload,
 };
 
+#[cfg(feature = "graceful")]
+use crate::graceful_shutdown::CloseToken;
+pub use capture::{CapturedEvent, LogCapture};
+use format::{JsonFormat, TextFormat};
+pub use hooks::log_report;
+pub use otel::OtelConfig;
+pub use query::{LogEntry, LogQuery, LogReader};
+use rate_limit::RateLimit;
+pub use rate_limit::{RateLimitConfig, RateLimitKey};
+use redact::{JsonFields, RedactFields, RedactScope, Redactor};
+pub use redact::{RedactConfig, Secret};
+use rolling::RollingWriter;
+pub use rolling::{RollingFileConfig, Rotation};
+use time::Timer;
+pub use time::{TimeConfig, TimeFormat, Timezone};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LogFormat {
+    #[default]
+    Compact,
+    Pretty,
+    /// one JSON object per line
+    Json,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LogTarget {
+    #[default]
+    Stdout,
+    Stderr,
+    RollingFile(RollingFileConfig),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BufferFull {
+    /// drop new lines until the writer catches up
+    #[default]
+    Drop,
+    /// block the logging thread
+    Block,
+}
+
+/// Writes through a background thread, see `tracing_appender::non_blocking`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NonBlockingConfig {
+    pub buffered_lines: usize,
+    pub when_full: BufferFull,
+}
+
+impl Default for NonBlockingConfig {
+    fn default() -> Self {
+        Self {
+            buffered_lines: tracing_appender::non_blocking::DEFAULT_BUFFERED_LINES_LIMIT,
+            when_full: Default::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SinkConfig {
+    pub target: LogTarget,
+    /// applied on top of `LogConfig.filter`
+    pub filter: Option<String>,
+    pub format: LogFormat,
+    /// defaults to on for stdout/stderr and off for files
+    pub ansi: Option<bool>,
+    pub non_blocking: Option<NonBlockingConfig>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct LogConfig {
-    filter: String,
-    rolling_file: Option<(String, String)>,
+    pub filter: String,
+    /// used with `format` when `sinks` is empty
+    pub rolling_file: Option<RollingFileConfig>,
+    /// writes `rolling_file` or stdout through a background thread, so slow
+    /// disks do not block; off by default, as lines can be dropped or lost on exit
+    pub non_blocking: Option<NonBlockingConfig>,
+    pub format: LogFormat,
+    pub time: TimeConfig,
+    /// added to every line, e.g. service name and version
+    pub fields: BTreeMap<String, String>,
+    pub sinks: Vec<SinkConfig>,
+    pub rate_limit: Option<RateLimitConfig>,
+    pub redact: RedactConfig,
+    /// installs color-eyre and logs panics through the sinks, on global init only
+    pub install_hooks: bool,
+    /// exports spans as OpenTelemetry traces, needs the `otel` feature
+    pub otel: Option<OtelConfig>,
 }
 
 impl Default for LogConfig {
@@ -16,74 +127,574 @@ impl Default for LogConfig {
         Self {
             filter: "info".to_owned(),
             rolling_file: Default::default(),
+            non_blocking: Default::default(),
+            format: Default::default(),
+            time: Default::default(),
+            fields: Default::default(),
+            sinks: Default::default(),
+            rate_limit: Default::default(),
+            redact: Default::default(),
+            install_hooks: false,
+            otel: Default::default(),
+        }
+    }
+}
+
+impl LogConfig {
+    fn sinks(&self) -> Vec<SinkConfig> {
+        if !self.sinks.is_empty() {
+            return self.sinks.clone();
+        }
+        let target = match &self.rolling_file {
+            Some(rolling_file) => LogTarget::RollingFile(rolling_file.clone()),
+            None => LogTarget::Stdout,
+        };
+        vec![SinkConfig {
+            target,
+            format: self.format,
+            non_blocking: self.non_blocking.clone(),
+            ..Default::default()
+        }]
+    }
+}
+
+type FilterLayer = reload::Layer<EnvFilter, Registry>;
+type Base = Layered<FilterLayer, Registry>;
+type BoxedLayer = Box<dyn Layer<Base> + Send + Sync>;
+
+/// Handle to the installed subscriber, used to change the filter at runtime.
+///
+/// Also owns the guards of non-blocking writers and the trace exporter: they are
+/// flushed and stopped by `shutdown` or once the last clone is dropped.
+#[derive(Debug, Clone)]
+#[must_use = "non-blocking sinks and the trace exporter shut down once the LogHandle is dropped"]
+pub struct LogHandle {
+    filter: reload::Handle<EnvFilter, Registry>,
+    /// as last set, `EnvFilter`'s `Display` reorders directives
+    directives: Arc<Mutex<String>>,
+    writers: Arc<Mutex<Writers>>,
+}
+
+#[derive(Debug, Default)]
+struct Writers {
+    guards: Vec<WorkerGuard>,
+    tracer_provider: Option<otel::TracerProvider>,
+}
+
+impl Writers {
+    fn shutdown(&mut self) {
+        self.guards.clear();
+        if let Some(provider) = self.tracer_provider.take() {
+            provider.shutdown().ok();
+        }
+    }
+}
+
+impl Drop for Writers {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+impl LogHandle {
+    pub fn filter(&self) -> Result<String> {
+        Ok(self.filter.with_current(|f| f.to_string())?)
+    }
+
+    pub fn set_filter(&self, filter: &str) -> Result<()> {
+        self.filter.reload(EnvFilter::try_new(filter)?)?;
+        *self.directives.lock().unwrap() = filter.to_owned();
+        info!("log filter set to {filter}");
+        Ok(())
+    }
+
+    /// Applies the parts of `config` that can change without restarting, i.e. `filter`.
+    pub fn apply(&self, config: &LogConfig) -> Result<()> {
+        if *self.directives.lock().unwrap() != config.filter {
+            self.set_filter(&config.filter)?;
         }
+        Ok(())
+    }
+
+    /// Flushes and stops the non-blocking writers and the trace exporter, those
+    /// sinks writing nothing afterwards.
+    pub fn shutdown(&self) {
+        self.writers.lock().unwrap().shutdown();
+    }
+
+    /// Calls `shutdown` once `token` is closed, from a child token so that
+    /// `token.closed()` only returns after the flush.
+    ///
+    /// The watcher does not keep the writers alive, dropping the last handle
+    /// still shuts them down right away.
+    #[cfg(feature = "graceful")]
+    pub fn shutdown_on_close(&self, token: &CloseToken) {
+        let child = token.child_token();
+        let writers = Arc::downgrade(&self.writers);
+        std::thread::spawn(move || {
+            child.closed();
+            if let Some(writers) = writers.upgrade() {
+                writers.lock().unwrap().shutdown();
+            }
+            drop(child);
+        });
     }
 }
 
-pub fn init_log_filter(filter: &str) {
-    set_log(Some(LogConfig {
+pub fn init_log_filter(filter: &str) -> LogHandle {
+    try_init_log_filter(filter).expect("setting default subscriber failed")
+}
+
+pub fn init_log(config: LogConfig) -> LogHandle {
+    try_init_log(config).expect("setting default subscriber failed")
+}
+
+/// Writes synchronously, `init_log` with `LogConfig.non_blocking` writes off
+/// the logging threads.
+pub fn init_log_file(filter: &str, directory: &str, file_name_prefix: &str) -> LogHandle {
+    try_init_log_file(filter, directory, file_name_prefix)
+        .expect("setting default subscriber failed")
+}
+
+pub fn try_init_log_filter(filter: &str) -> Result<LogHandle> {
+    set_log(LogConfig {
         filter: filter.to_owned(),
         ..Default::default()
-    }));
+    })
 }
 
-pub fn init_log(config: LogConfig) {
-    set_log(Some(config));
+pub fn try_init_log(config: LogConfig) -> Result<LogHandle> {
+    set_log(config)
 }
 
-pub fn init_log_file(filter: &str, directory: &str, file_name_prefix: &str) {
-    set_log(Some(LogConfig {
+pub fn try_init_log_file(
+    filter: &str,
+    directory: &str,
+    file_name_prefix: &str,
+) -> Result<LogHandle> {
+    set_log(LogConfig {
         filter: filter.to_owned(),
-        rolling_file: Some((directory.to_owned(), file_name_prefix.to_owned())),
-    }))
+        rolling_file: Some(RollingFileConfig {
+            directory: directory.to_owned(),
+            prefix: file_name_prefix.to_owned(),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
 }
 
-fn set_log(log_config: Option<LogConfig>) {
-    let log_config = log_config.unwrap_or_default();
-    struct LocalTimer;
-    impl FormatTime for LocalTimer {
-        fn format_time(&self, w: &mut Writer<'_>) -> std::fmt::Result {
-            write!(w, "{}", chrono::Local::now().format("%m-%d %T%.3f"))
-        }
+/// Installs `config` for the current thread only, until the guard is dropped.
+/// Unlike `init_log`, it leaves the global OpenTelemetry provider and propagator alone.
+pub fn scoped_log(config: &LogConfig) -> Result<(LogHandle, DefaultGuard)> {
+    let (dispatch, handle) = build(config)?;
+    Ok((handle, tracing::dispatcher::set_default(&dispatch)))
+}
+
+/// Writers of the global subscriber, for the panic hook to shut down.
+static GLOBAL_WRITERS: OnceLock<Weak<Mutex<Writers>>> = OnceLock::new();
+
+/// Flushes and stops the non-blocking writers of the global subscriber.
+fn shutdown_global() {
+    if let Some(writers) = GLOBAL_WRITERS.get().and_then(Weak::upgrade) {
+        // the lock may be poisoned by the panic being reported
+        writers.lock().unwrap_or_else(|e| e.into_inner()).shutdown();
+    }
+}
+
+fn set_log(log_config: LogConfig) -> Result<LogHandle> {
+    let (dispatch, handle) = build(&log_config)?;
+    // before the subscriber, so failing here leaves the process untouched
+    if log_config.install_hooks {
+        ensure!(
+            !tracing::dispatcher::has_been_set(),
+            "a global subscriber is already set"
+        );
+        hooks::install()?;
+    }
+    tracing::dispatcher::set_global_default(dispatch)?;
+    GLOBAL_WRITERS.set(Arc::downgrade(&handle.writers)).ok();
+    if let Some(provider) = &handle.writers.lock().unwrap().tracer_provider {
+        otel::set_global(provider);
+    }
+    Ok(handle)
+}
+
+fn fmt_layer<W>(
+    format: LogFormat,
+    fields: &BTreeMap<String, String>,
+    timer: Timer,
+    redactor: Arc<Redactor>,
+    writer: W,
+    ansi: bool,
+) -> BoxedLayer
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let fmt = tracing_subscriber::fmt::layer()
+        .with_ansi(ansi)
+        .with_writer(writer);
+    let text = tracing_subscriber::fmt::format()
+        .with_timer(timer.clone())
+        .with_thread_ids(true);
+    match format {
+        LogFormat::Compact => fmt
+            .fmt_fields(RedactFields::new(DefaultFields::new(), redactor))
+            .event_format(TextFormat::new(text.compact(), fields))
+            .boxed(),
+        LogFormat::Pretty => fmt
+            .fmt_fields(RedactFields::new(PrettyFields::new(), redactor))
+            .event_format(TextFormat::new(text.pretty(), fields))
+            .boxed(),
+        LogFormat::Json => fmt
+            .fmt_fields(JsonFields::new(redactor.clone()))
+            .event_format(JsonFormat {
+                timer,
+                fields: fields.clone(),
+                redactor,
+            })
+            .boxed(),
     }
-    let filter = EnvFilter::new(&log_config.filter);
+}
+
+fn sink_writer(sink: &SinkConfig, guards: &mut Vec<WorkerGuard>) -> Result<BoxMakeWriter> {
+    let Some(non_blocking) = &sink.non_blocking else {
+        return Ok(match &sink.target {
+            LogTarget::Stdout => BoxMakeWriter::new(std::io::stdout),
+            LogTarget::Stderr => BoxMakeWriter::new(std::io::stderr),
+            LogTarget::RollingFile(config) => {
+                BoxMakeWriter::new(Mutex::new(RollingWriter::new(config.clone())?))
+            }
+        });
+    };
+    let writer: Box<dyn Write + Send> = match &sink.target {
+        LogTarget::Stdout => Box::new(std::io::stdout()),
+        LogTarget::Stderr => Box::new(std::io::stderr()),
+        LogTarget::RollingFile(config) => Box::new(RollingWriter::new(config.clone())?),
+    };
+    let (writer, guard) = NonBlockingBuilder::default()
+        .buffered_lines_limit(non_blocking.buffered_lines)
+        .lossy(non_blocking.when_full == BufferFull::Drop)
+        .finish(writer);
+    guards.push(guard);
+    Ok(BoxMakeWriter::new(writer))
+}
+
+fn sink_layer(
+    sink: &SinkConfig,
+    fields: &BTreeMap<String, String>,
+    timer: Timer,
+    redactor: Arc<Redactor>,
+    guards: &mut Vec<WorkerGuard>,
+) -> Result<BoxedLayer> {
+    let ansi = sink
+        .ansi
+        .unwrap_or(!matches!(sink.target, LogTarget::RollingFile(_)));
+    let layer = fmt_layer(
+        sink.format,
+        fields,
+        timer,
+        redactor,
+        sink_writer(sink, guards)?,
+        ansi,
+    );
+    Ok(match &sink.filter {
+        Some(filter) => layer.with_filter(EnvFilter::try_new(filter)?).boxed(),
+        None => layer,
+    })
+}
 
-    let mut logfile = None;
-    let mut stdout = None;
-    if let Some((directory, file_name_prefix)) = &log_config.rolling_file {
-        // logfile
-        logfile = Some(tracing_appender::rolling::daily(
-            directory,
-            file_name_prefix,
-        ));
-    } else {
-        // stdout
-        stdout = Some(std::io::stdout.with_max_level(tracing::Level::TRACE));
+fn build(log_config: &LogConfig) -> Result<(Dispatch, LogHandle)> {
+    let (filter, filter_handle) = reload::Layer::new(EnvFilter::try_new(&log_config.filter)?);
+
+    let timer = Timer::new(&log_config.time)?;
+    let redactor = Arc::new(Redactor::new(&log_config.redact)?);
+    let rate_limit = log_config.rate_limit.as_ref().map(RateLimit::new);
+    let mut guards = Vec::new();
+    let mut layers: Vec<BoxedLayer> = rate_limit
+        .iter()
+        .map(|rate_limit| rate_limit.clone().boxed())
+        .collect();
+    if log_config.install_hooks {
+        layers.push(tracing_error::ErrorLayer::default().boxed());
+    }
+    let mut sinks = Vec::new();
+    for sink in log_config.sinks() {
+        sinks.push(sink_layer(
+            &sink,
+            &log_config.fields,
+            timer.clone(),
+            redactor.clone(),
+            &mut guards,
+        )?);
     }
+    layers.push(RedactScope::new(sinks, redactor.clone()).boxed());
+    let tracer_provider = match &log_config.otel {
+        Some(otel) => {
+            let (layer, provider) = otel::layer(otel, redactor)?;
+            layers.push(layer);
+            Some(provider)
+        }
+        None => None,
+    };
 
     // tracing 初始化
-    if let Some(stdout) = stdout {
-        let subscriber = tracing_subscriber::fmt()
-            .compact()
-            .with_max_level(tracing::Level::TRACE)
-            .with_timer(LocalTimer)
-            .with_thread_ids(true)
-            .with_env_filter(filter)
-            .with_writer(stdout)
-            .finish();
-        tracing::subscriber::set_global_default(subscriber)
-            .expect("setting default subscriber failed");
-    } else {
-        let subscriber = tracing_subscriber::fmt()
-            .compact()
-            .with_max_level(tracing::Level::TRACE)
-            .with_timer(LocalTimer)
-            .with_thread_ids(true)
-            .with_ansi(false)
-            .with_env_filter(filter)
-            .with_writer(logfile.unwrap())
-            .finish();
-        tracing::subscriber::set_global_default(subscriber)
-            .expect("setting default subscriber failed");
+    let dispatch = Dispatch::new(tracing_subscriber::registry().with(filter).with(layers));
+    if let Some(rate_limit) = rate_limit {
+        rate_limit.spawn_summary(dispatch.downgrade());
+    }
+    Ok((
+        dispatch,
+        LogHandle {
+            filter: filter_handle,
+            directives: Arc::new(Mutex::new(log_config.filter.clone())),
+            writers: Arc::new(Mutex::new(Writers {
+                guards,
+                tracer_provider,
+            })),
+        },
+    ))
+}
+
+#[test]
+fn test() -> Result<()> {
+    let (dispatch, handle) = build(&LogConfig::default())?;
+    tracing::dispatcher::with_default(&dispatch, || {
+        assert!(!tracing::enabled!(tracing::Level::DEBUG));
+        handle.set_filter("debug")?;
+        assert_eq!(handle.filter()?, "debug");
+        assert!(tracing::enabled!(tracing::Level::DEBUG));
+        assert!(handle.set_filter("[[").is_err());
+        handle.apply(&LogConfig::default())?;
+        assert!(!tracing::enabled!(tracing::Level::DEBUG));
+        Ok::<_, color_eyre::Report>(())
+    })?;
+
+    // an unchanged filter is not reloaded, even when displayed in another order
+    let config = LogConfig {
+        filter: "warn,common_x=debug".to_owned(),
+        ..Default::default()
+    };
+    let (_dispatch, handle) = build(&config)?;
+    let (capture, _guard) = LogCapture::scoped("info")?;
+    handle.apply(&config)?;
+    handle.apply(&config)?;
+    assert!(!capture.contains(tracing::Level::INFO, "log filter set to"));
+    handle.apply(&LogConfig::default())?;
+    assert!(capture.contains(tracing::Level::INFO, "log filter set to info"));
+
+    let bad = LogConfig {
+        filter: "[[".to_owned(),
+        ..Default::default()
+    };
+    assert!(scoped_log(&bad).is_err());
+    let bad = LogConfig {
+        sinks: vec![SinkConfig {
+            filter: Some("[[".to_owned()),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    assert!(scoped_log(&bad).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_json() -> Result<()> {
+    use crate::test_util::Buffer;
+
+    let config = LogConfig {
+        format: LogFormat::Json,
+        fields: BTreeMap::from([("service".to_owned(), "common_x".to_owned())]),
+        ..Default::default()
+    };
+    let buffer = Buffer::default();
+    let writer = buffer.clone();
+    let (filter, _) = reload::Layer::new(EnvFilter::new(&config.filter));
+    let subscriber = tracing_subscriber::registry().with(filter).with(fmt_layer(
+        config.format,
+        &config.fields,
+        Timer::new(&config.time)?,
+        Arc::new(Redactor::new(&config.redact)?),
+        move || writer.clone(),
+        false,
+    ));
+    tracing::subscriber::with_default(subscriber, || {
+        let _span = tracing::info_span!("request", id = 7).entered();
+        info!(user = "jl", "handled");
+    });
+
+    let output = buffer.contents();
+    let line: serde_json::Value = serde_json::from_str(output.trim())?;
+    assert_eq!(line["service"], "common_x");
+    assert_eq!(line["level"], "INFO");
+    assert_eq!(line["target"], "common_x::log");
+    assert_eq!(line["fields"]["message"], "handled");
+    assert_eq!(line["fields"]["user"], "jl");
+    assert_eq!(line["span"]["name"], "request");
+    assert_eq!(line["span"]["id"], 7);
+    assert!(chrono::DateTime::parse_from_rfc3339(line["timestamp"].as_str().unwrap()).is_ok());
+    Ok(())
+}
+
+#[test]
+fn test_sinks() -> Result<()> {
+    let directory = std::env::temp_dir().join("common_x_log_sinks_test");
+    std::fs::remove_dir_all(&directory).ok();
+    let file_sink = |prefix: &str, filter: &str, non_blocking| SinkConfig {
+        target: LogTarget::RollingFile(RollingFileConfig {
+            directory: directory.to_string_lossy().into_owned(),
+            prefix: prefix.to_owned(),
+            ..Default::default()
+        }),
+        filter: Some(filter.to_owned()),
+        non_blocking,
+        ..Default::default()
+    };
+    let config = LogConfig {
+        filter: "debug".to_owned(),
+        sinks: vec![
+            file_sink("warn", "warn", None),
+            file_sink("all", "debug", Some(Default::default())),
+        ],
+        ..Default::default()
+    };
+    let (dispatch, handle) = build(&config)?;
+    tracing::dispatcher::with_default(&dispatch, || {
+        tracing::debug!("debug line");
+        tracing::warn!("warn line");
+    });
+    handle.shutdown();
+
+    let read = |prefix: &str| -> Result<String> {
+        let mut content = String::new();
+        for entry in std::fs::read_dir(&directory)? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().starts_with(prefix) {
+                content += &std::fs::read_to_string(entry.path())?;
+            }
+        }
+        Ok(content)
+    };
+    let (warn, all) = (read("warn")?, read("all")?);
+    assert!(!warn.contains("debug line") && warn.contains("warn line"));
+    assert!(all.contains("debug line") && all.contains("warn line"));
+    assert!(!all.contains('\x1b'));
+    std::fs::remove_dir_all(&directory)?;
+
+    // the legacy file sink is only non-blocking on request
+    let mut legacy = LogConfig {
+        rolling_file: Some(Default::default()),
+        ..Default::default()
+    };
+    assert!(legacy.sinks()[0].non_blocking.is_none());
+    legacy.non_blocking = Some(Default::default());
+    assert!(legacy.sinks()[0].non_blocking.is_some());
+    Ok(())
+}
+
+#[cfg(feature = "graceful")]
+#[test]
+fn test_shutdown_on_close() -> Result<()> {
+    let directory = std::env::temp_dir().join("common_x_log_flush_test");
+    std::fs::remove_dir_all(&directory).ok();
+    let config = |prefix: &str| LogConfig {
+        sinks: vec![SinkConfig {
+            target: LogTarget::RollingFile(RollingFileConfig {
+                directory: directory.to_string_lossy().into_owned(),
+                prefix: prefix.to_owned(),
+                ..Default::default()
+            }),
+            non_blocking: Some(Default::default()),
+            ..Default::default()
+        }],
+        ..Default::default()
     };
+    let read = |prefix: &str| -> Result<usize> {
+        let mut lines = 0;
+        for entry in std::fs::read_dir(&directory)? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().starts_with(prefix) {
+                lines += std::fs::read_to_string(entry.path())?.lines().count();
+            }
+        }
+        Ok(lines)
+    };
+
+    let token = CloseToken::default();
+    let (dispatch, handle) = build(&config("closed"))?;
+    handle.shutdown_on_close(&token);
+    tracing::dispatcher::with_default(&dispatch, || {
+        for i in 0..500 {
+            info!("line {i}");
+        }
+    });
+    token.close();
+    token.closed();
+    assert_eq!(read("closed")?, 500);
+
+    // an unclosed token must not keep the writers alive
+    let token = CloseToken::default();
+    let (dispatch, handle) = build(&config("dropped"))?;
+    handle.shutdown_on_close(&token);
+    tracing::dispatcher::with_default(&dispatch, || {
+        for i in 0..500 {
+            info!("line {i}");
+        }
+    });
+    drop(handle);
+    let lines = read("dropped")?;
+    token.close();
+    assert_eq!(lines, 500);
+    std::fs::remove_dir_all(&directory)?;
+    Ok(())
+}
+
+#[test]
+fn test_init_log_file() -> Result<()> {
+    let directory = std::env::temp_dir().join("common_x_log_init_test");
+    std::fs::remove_dir_all(&directory).ok();
+    let handle = try_init_log_file("info", &directory.to_string_lossy(), "app")?;
+    assert!(try_init_log_filter("info").is_err());
+    let hooks = LogConfig {
+        install_hooks: true,
+        ..Default::default()
+    };
+    assert!(try_init_log(hooks).is_err());
+    for i in 0..10 {
+        info!(target: "init_log_file", "line {i}");
+    }
+    // the file sink is written synchronously, dropping the handle loses nothing
+    drop(handle);
+
+    let read = || -> Result<usize> {
+        let mut lines = 0;
+        for entry in std::fs::read_dir(&directory)? {
+            let content = std::fs::read_to_string(entry?.path())?;
+            lines += content
+                .lines()
+                .filter(|l| l.contains("init_log_file"))
+                .count();
+        }
+        Ok(lines)
+    };
+    assert_eq!(read()?, 10);
+    info!(target: "init_log_file", "after drop");
+    assert_eq!(read()?, 11);
+    std::fs::remove_dir_all(&directory)?;
+    Ok(())
+}
+
+#[test]
+fn test_capture() -> Result<()> {
+    let (capture, _guard) = LogCapture::scoped("debug")?;
+    tracing::trace!("hidden");
+    tracing::debug!(attempt = 2, "retrying");
+    tracing::warn!("gave up");
+    assert_eq!(capture.events().len(), 2);
+    assert!(capture.contains(tracing::Level::DEBUG, "retrying"));
+    assert_eq!(capture.events()[0].fields["attempt"], 2);
+    assert!(!capture.contains(tracing::Level::TRACE, "hidden"));
+    capture.clear();
+    assert!(capture.events().is_empty());
+    Ok(())
 }
@@ -0,0 +1,74 @@
+use std::sync::{Arc, Mutex};
+
+use color_eyre::Result;
+use serde_json::{Map, Value};
+use tracing::{Event, Level, Subscriber, subscriber::DefaultGuard};
+use tracing_subscriber::{EnvFilter, Layer, layer::Context, layer::SubscriberExt};
+
+use super::format::JsonVisitor;
+
+#[derive(Debug, Clone)]
+pub struct CapturedEvent {
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+    /// every field but `message`
+    pub fields: Map<String, Value>,
+}
+
+/// Layer keeping emitted events in memory, for tests to assert on.
+#[derive(Debug, Clone, Default)]
+pub struct LogCapture {
+    events: Arc<Mutex<Vec<CapturedEvent>>>,
+}
+
+impl LogCapture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Captures the current thread's events passing `filter` until the guard is dropped.
+    pub fn scoped(filter: &str) -> Result<(Self, DefaultGuard)> {
+        let capture = Self::new();
+        let subscriber = tracing_subscriber::registry()
+            .with(EnvFilter::try_new(filter)?)
+            .with(capture.clone());
+        Ok((capture, tracing::subscriber::set_default(subscriber)))
+    }
+
+    pub fn events(&self) -> Vec<CapturedEvent> {
+        self.events.lock().unwrap().clone()
+    }
+
+    pub fn contains(&self, level: Level, message: &str) -> bool {
+        self.events
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|e| e.level == level && e.message.contains(message))
+    }
+
+    pub fn clear(&self) {
+        self.events.lock().unwrap().clear();
+    }
+}
+
+impl<S: Subscriber> Layer<S> for LogCapture {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = JsonVisitor::default();
+        event.record(&mut visitor);
+        let mut fields = visitor.0;
+        let message = match fields.remove("message") {
+            Some(Value::String(message)) => message,
+            Some(message) => message.to_string(),
+            None => String::new(),
+        };
+        let metadata = event.metadata();
+        self.events.lock().unwrap().push(CapturedEvent {
+            level: *metadata.level(),
+            target: metadata.target().to_owned(),
+            message,
+            fields,
+        });
+    }
+}
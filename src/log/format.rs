@@ -0,0 +1,142 @@
+use std::{collections::BTreeMap, fmt, sync::Arc};
+
+use serde_json::{Map, Value};
+use tracing::{
+    Event, Subscriber,
+    field::{Field, Visit},
+};
+use tracing_subscriber::{
+    fmt::{
+        FmtContext, FormatEvent, FormatFields, FormattedFields, format::Writer, time::FormatTime,
+    },
+    registry::LookupSpan,
+};
+
+use super::redact::{Redact, Redactor};
+
+/// One JSON object per line, with the current span, the span list, target,
+/// thread id and the static fields at the top level.
+pub(crate) struct JsonFormat<T> {
+    pub(crate) timer: T,
+    pub(crate) fields: BTreeMap<String, String>,
+    pub(crate) redactor: Arc<Redactor>,
+}
+
+impl<S, N, T> FormatEvent<S, N> for JsonFormat<T>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+    T: FormatTime,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut timestamp = String::new();
+        self.timer.format_time(&mut Writer::new(&mut timestamp))?;
+        let metadata = event.metadata();
+
+        let mut line = Map::new();
+        for (key, value) in &self.fields {
+            line.insert(key.clone(), value.clone().into());
+        }
+        line.insert("timestamp".into(), timestamp.into());
+        line.insert("level".into(), metadata.level().as_str().into());
+        line.insert("target".into(), metadata.target().into());
+        line.insert(
+            "thread_id".into(),
+            format!("{:?}", std::thread::current().id()).into(),
+        );
+        let mut visitor = Redact::new(JsonVisitor::default(), self.redactor.clone());
+        event.record(&mut visitor);
+        line.insert("fields".into(), Value::Object(visitor.inner.0));
+
+        if let Some(scope) = ctx.event_scope() {
+            let spans: Vec<Value> = scope
+                .from_root()
+                .map(|span| {
+                    let mut object = Map::new();
+                    object.insert("name".into(), span.name().into());
+                    let extensions = span.extensions();
+                    if let Some(fields) = extensions.get::<FormattedFields<N>>()
+                        && let Ok(Value::Object(fields)) = serde_json::from_str(fields)
+                    {
+                        object.extend(fields);
+                    }
+                    Value::Object(object)
+                })
+                .collect();
+            if let Some(span) = spans.last() {
+                line.insert("span".into(), span.clone());
+            }
+            line.insert("spans".into(), spans.into());
+        }
+
+        writeln!(writer, "{}", Value::Object(line))
+    }
+}
+
+/// Prefixes text lines with the static fields as `key=value`.
+pub(crate) struct TextFormat<F> {
+    pub(crate) inner: F,
+    pub(crate) prefix: String,
+}
+
+impl<F> TextFormat<F> {
+    pub(crate) fn new(inner: F, fields: &BTreeMap<String, String>) -> Self {
+        let prefix = fields
+            .iter()
+            .map(|(key, value)| format!("{key}={value} "))
+            .collect();
+        Self { inner, prefix }
+    }
+}
+
+impl<S, N, F> FormatEvent<S, N> for TextFormat<F>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+    F: FormatEvent<S, N>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        writer.write_str(&self.prefix)?;
+        self.inner.format_event(ctx, writer, event)
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct JsonVisitor(pub(crate) Map<String, Value>);
+
+impl Visit for JsonVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().into(), format!("{value:?}").into());
+    }
+}
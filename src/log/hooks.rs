@@ -0,0 +1,71 @@
+use std::panic::PanicHookInfo;
+
+use color_eyre::{
+    Report, Result,
+    config::{HookBuilder, PanicHook, Theme},
+};
+use tracing::error;
+
+/// Installs color-eyre, with panics logged as `error` events of target `panic`
+/// and the report still printed to stderr.
+///
+/// Backtraces follow `RUST_BACKTRACE`/`RUST_LIB_BACKTRACE`, span traces are
+/// captured by the `ErrorLayer` added to the subscriber.
+pub(crate) fn install() -> Result<()> {
+    let (panic_hook, eyre_hook) = HookBuilder::default()
+        .theme(Theme::new())
+        .display_env_section(false)
+        .into_hooks();
+    eyre_hook.install()?;
+    std::panic::set_hook(Box::new(log_panic(panic_hook)));
+    Ok(())
+}
+
+/// A panic that ends the process also flushes the non-blocking writers, which
+/// stop writing afterwards, so that the report reaches the files.
+fn log_panic(panic_hook: PanicHook) -> impl Fn(&PanicHookInfo<'_>) + Send + Sync + 'static {
+    move |info| {
+        let report = panic_hook.panic_report(info);
+        error!(target: "panic", "{report}");
+        if cfg!(panic = "abort") || std::thread::current().name() == Some("main") {
+            super::shutdown_global();
+        }
+        eprintln!("{report}");
+    }
+}
+
+/// Logs `report` with its span trace and backtrace, e.g. before exiting on an error.
+pub fn log_report(report: &Report) {
+    error!("{report:?}");
+}
+
+#[test]
+fn test() -> Result<()> {
+    use tracing::Level;
+
+    use super::LogCapture;
+
+    let (capture, _guard) = LogCapture::scoped("info")?;
+    // only this thread's panic goes to the hook, others' still reach the previous one
+    let hook = log_panic(HookBuilder::default().theme(Theme::new()).into_hooks().0);
+    let previous = std::sync::Arc::new(std::panic::take_hook());
+    let thread = std::thread::current().id();
+    let forward = previous.clone();
+    std::panic::set_hook(Box::new(move |info| {
+        if std::thread::current().id() == thread {
+            hook(info);
+        } else {
+            forward(info);
+        }
+    }));
+    let result = std::panic::catch_unwind(|| panic!("boom"));
+    std::panic::set_hook(Box::new(move |info| previous(info)));
+    assert!(result.is_err());
+    let events = capture.events();
+    assert_eq!(events[0].target, "panic");
+    assert!(capture.contains(Level::ERROR, "boom"));
+
+    log_report(&color_eyre::eyre::eyre!("failed to start"));
+    assert!(capture.contains(Level::ERROR, "failed to start"));
+    Ok(())
+}
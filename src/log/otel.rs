@@ -0,0 +1,223 @@
+#[cfg(feature = "otel")]
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use color_eyre::Result;
+#[cfg(not(feature = "otel"))]
+use color_eyre::eyre::bail;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "otel")]
+use super::redact::MASK;
+use super::{BoxedLayer, redact::Redactor};
+
+/// OTLP/HTTP trace export, requires the `otel` feature.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OtelConfig {
+    pub endpoint: String,
+    pub service_name: String,
+    /// share of new traces sampled, sampled parents are always followed
+    pub sample_ratio: f64,
+}
+
+impl Default for OtelConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: "http://localhost:4318/v1/traces".to_owned(),
+            service_name: env!("CARGO_PKG_NAME").to_owned(),
+            sample_ratio: 1.0,
+        }
+    }
+}
+
+#[cfg(feature = "otel")]
+pub(crate) type TracerProvider = opentelemetry_sdk::trace::SdkTracerProvider;
+
+#[cfg(not(feature = "otel"))]
+#[derive(Debug, Clone)]
+pub(crate) struct TracerProvider;
+
+#[cfg(not(feature = "otel"))]
+impl TracerProvider {
+    pub(crate) const fn shutdown(&self) -> Result<(), ()> {
+        Ok(())
+    }
+}
+
+/// Span exporter masking attributes with the sinks' `Redactor` before `inner` sends them.
+#[cfg(feature = "otel")]
+#[derive(Debug)]
+struct RedactExporter<E> {
+    inner: E,
+    redactor: Arc<Redactor>,
+}
+
+#[cfg(feature = "otel")]
+impl<E> RedactExporter<E> {
+    fn redact(&self, attributes: &mut [opentelemetry::KeyValue]) {
+        use opentelemetry::Value;
+
+        for attribute in attributes {
+            if self.redactor.masks_name(attribute.key.as_str()) {
+                attribute.value = MASK.into();
+            } else if let Value::String(value) = &attribute.value
+                && let Cow::Owned(value) = self.redactor.redact(value.as_str())
+            {
+                attribute.value = value.into();
+            }
+        }
+    }
+}
+
+#[cfg(feature = "otel")]
+impl<E: opentelemetry_sdk::trace::SpanExporter> opentelemetry_sdk::trace::SpanExporter
+    for RedactExporter<E>
+{
+    async fn export(
+        &self,
+        mut batch: Vec<opentelemetry_sdk::trace::SpanData>,
+    ) -> opentelemetry_sdk::error::OTelSdkResult {
+        for span in &mut batch {
+            self.redact(&mut span.attributes);
+            for event in &mut span.events.events {
+                if let Cow::Owned(name) = self.redactor.redact(&event.name) {
+                    event.name = name.into();
+                }
+                self.redact(&mut event.attributes);
+            }
+        }
+        self.inner.export(batch).await
+    }
+
+    fn shutdown_with_timeout(
+        &self,
+        timeout: std::time::Duration,
+    ) -> opentelemetry_sdk::error::OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn force_flush(&self) -> opentelemetry_sdk::error::OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn set_resource(&mut self, resource: &opentelemetry_sdk::Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+/// Span attributes and events are redacted like the other sinks before export.
+#[cfg(feature = "otel")]
+pub(crate) fn layer(
+    config: &OtelConfig,
+    redactor: Arc<Redactor>,
+) -> Result<(BoxedLayer, TracerProvider)> {
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::{Resource, trace::Sampler};
+    use tracing_subscriber::Layer;
+
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(&config.endpoint)
+        .build()?;
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(RedactExporter {
+            inner: exporter,
+            redactor,
+        })
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            config.sample_ratio,
+        ))))
+        .with_resource(
+            Resource::builder()
+                .with_service_name(config.service_name.clone())
+                .build(),
+        )
+        .build();
+    let layer = tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer(env!("CARGO_PKG_NAME")))
+        .boxed();
+    Ok((layer, provider))
+}
+
+/// Makes `provider` and the W3C trace context propagator the process-wide defaults.
+#[cfg(feature = "otel")]
+pub(crate) fn set_global(provider: &TracerProvider) {
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
+
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+    opentelemetry::global::set_tracer_provider(provider.clone());
+}
+
+#[cfg(not(feature = "otel"))]
+pub(crate) const fn set_global(_provider: &TracerProvider) {}
+
+#[cfg(not(feature = "otel"))]
+pub(crate) fn layer(
+    _config: &OtelConfig,
+    _redactor: Arc<Redactor>,
+) -> Result<(BoxedLayer, TracerProvider)> {
+    bail!("LogConfig.otel needs the otel feature")
+}
+
+#[cfg(feature = "otel")]
+#[test]
+fn test() -> Result<()> {
+    use std::{
+        io::{BufRead, Read, Write},
+        sync::mpsc,
+        time::Duration,
+    };
+
+    use super::{LogConfig, scoped_log};
+    use crate::test_util::serve;
+
+    // stands in for an OTLP collector, reporting each request line and body
+    let (tx, rx) = mpsc::channel();
+    let port = serve(move |reader, stream| {
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).ok();
+        let mut content_length = 0;
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).ok();
+            if header.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':')
+                && name.eq_ignore_ascii_case("content-length")
+            {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).ok();
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+            .ok();
+        tx.send((request_line, body)).ok();
+    })?;
+
+    let config = LogConfig {
+        otel: Some(OtelConfig {
+            endpoint: format!("http://127.0.0.1:{port}/v1/traces"),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let (handle, guard) = scoped_log(&config)?;
+    tracing::info_span!("work", password = "hunter2", note = "token=abc123")
+        .in_scope(|| tracing::info!(secret = "s3cr3t", "inside with token: t0k3n"));
+    drop(guard);
+    handle.shutdown();
+
+    let (request_line, body) = rx.recv_timeout(Duration::from_secs(10))?;
+    assert!(request_line.starts_with("POST /v1/traces"));
+    let body = String::from_utf8_lossy(&body);
+    assert!(body.contains("work") && body.contains("token=***"));
+    for secret in ["hunter2", "abc123", "s3cr3t", "t0k3n"] {
+        assert!(!body.contains(secret), "{secret} exported");
+    }
+    Ok(())
+}
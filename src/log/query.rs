@@ -0,0 +1,312 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use chrono::{DateTime, FixedOffset};
+use color_eyre::{Result, eyre::eyre};
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Deserializer, Serialize, de};
+use serde_json::Value;
+use tracing::Level;
+
+use super::{LogConfig, LogTarget, RollingFileConfig};
+
+/// Filters of `LogReader`, every one optional.
+///
+/// Time bounds only match lines with an RFC3339 timestamp.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogQuery {
+    /// most verbose level kept, e.g. `warn` keeps warnings and errors
+    pub level: Option<String>,
+    #[serde(deserialize_with = "offset_time")]
+    pub since: Option<DateTime<FixedOffset>>,
+    #[serde(deserialize_with = "offset_time")]
+    pub until: Option<DateTime<FixedOffset>>,
+    /// target prefix, e.g. `common_x::log`
+    pub target: Option<String>,
+    /// only the last `tail` matches
+    pub tail: Option<usize>,
+}
+
+impl LogQuery {
+    pub fn level(&self) -> Result<Option<Level>> {
+        self.level
+            .as_deref()
+            .map(|level| Level::from_str(level).map_err(|_| eyre!("invalid log level: {level}")))
+            .transpose()
+    }
+
+    fn matches(&self, entry: &LogEntry, level: Option<Level>) -> bool {
+        level.is_none_or(|level| entry.level.is_some_and(|l| l <= level))
+            && self
+                .since
+                .is_none_or(|since| entry.timestamp.is_some_and(|t| t >= since))
+            && self
+                .until
+                .is_none_or(|until| entry.timestamp.is_some_and(|t| t <= until))
+            && self.target.as_deref().is_none_or(|target| {
+                entry
+                    .target
+                    .as_deref()
+                    .is_some_and(|t| t.starts_with(target))
+            })
+    }
+}
+
+/// One event, with the lines following it that are not events themselves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    pub timestamp: Option<DateTime<FixedOffset>>,
+    pub level: Option<Level>,
+    pub target: Option<String>,
+    pub text: String,
+}
+
+impl LogEntry {
+    /// JSON lines are read by their fields, text lines by their first RFC3339
+    /// token, level and the first `target:` after the level and span names.
+    fn parse(line: &str) -> Option<Self> {
+        if line.starts_with('{') {
+            let value: Value = serde_json::from_str(line).ok()?;
+            return Some(Self {
+                timestamp: value["timestamp"]
+                    .as_str()
+                    .and_then(|t| DateTime::parse_from_rfc3339(t).ok()),
+                level: Some(value["level"].as_str()?.parse().ok()?),
+                target: value["target"].as_str().map(str::to_owned),
+                text: line.to_owned(),
+            });
+        }
+        let tokens: Vec<_> = line.split_whitespace().collect();
+        let position = tokens
+            .iter()
+            .position(|t| matches!(*t, "TRACE" | "DEBUG" | "INFO" | "WARN" | "ERROR"))?;
+        let mut prefixes = tokens[position + 1..]
+            .iter()
+            .skip_while(|t| t.starts_with("ThreadId("))
+            .map_while(|t| t.strip_suffix(':'));
+        let target = match (prefixes.next(), prefixes.next()) {
+            // `span:span: target:`, span names have no `::`
+            (Some(spans), Some(target)) if !spans.contains("::") && target.contains("::") => {
+                Some(target)
+            }
+            (target, _) => target,
+        };
+        Some(Self {
+            timestamp: tokens[..position]
+                .iter()
+                .find_map(|t| DateTime::parse_from_rfc3339(t).ok()),
+            level: tokens[position].parse().ok(),
+            target: target.map(str::to_owned),
+            text: line.to_owned(),
+        })
+    }
+}
+
+/// Reads the rotated files written by the rolling file sinks of a `LogConfig`.
+#[derive(Debug, Clone)]
+pub struct LogReader {
+    files: Vec<PathBuf>,
+}
+
+impl LogReader {
+    /// Files of every rolling file sink, sink by sink and oldest first.
+    pub fn new(config: &LogConfig) -> Result<Self> {
+        let mut files = Vec::new();
+        for sink in config.sinks() {
+            if let LogTarget::RollingFile(config) = &sink.target {
+                for file in Self::from_file_config(config)?.files {
+                    if !files.contains(&file) {
+                        files.push(file);
+                    }
+                }
+            }
+        }
+        Ok(Self { files })
+    }
+
+    pub fn from_file_config(config: &RollingFileConfig) -> Result<Self> {
+        Ok(Self {
+            files: config.files()?,
+        })
+    }
+
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    pub fn query(&self, query: &LogQuery) -> Result<Vec<LogEntry>> {
+        let mut entries = Vec::new();
+        self.scan(query, |entry| {
+            entries.push(entry);
+            true
+        })?;
+        Ok(entries)
+    }
+
+    /// Passes each match to `f` as the files are read, until it returns false.
+    ///
+    /// With `tail`, files are read newest first until enough matches are found.
+    pub fn scan(&self, query: &LogQuery, mut f: impl FnMut(LogEntry) -> bool) -> Result<()> {
+        let level = query.level()?;
+        let Some(n) = query.tail else {
+            for path in &self.files {
+                let read = read_file(path, |entry| !query.matches(&entry, level) || f(entry))?;
+                if !read {
+                    break;
+                }
+            }
+            return Ok(());
+        };
+
+        let mut tail = VecDeque::new();
+        for path in self.files.iter().rev() {
+            let wanted = n - tail.len();
+            if wanted == 0 {
+                break;
+            }
+            let mut last = VecDeque::with_capacity(wanted);
+            read_file(path, |entry| {
+                if query.matches(&entry, level) {
+                    if last.len() == wanted {
+                        last.pop_front();
+                    }
+                    last.push_back(entry);
+                }
+                true
+            })?;
+            for entry in last.into_iter().rev() {
+                tail.push_front(entry);
+            }
+        }
+        tail.into_iter().all(f);
+        Ok(())
+    }
+}
+
+/// Passes each entry of `path` to `f`, returning false once `f` does.
+fn read_file(path: &Path, mut f: impl FnMut(LogEntry) -> bool) -> Result<bool> {
+    let file = File::open(path)?;
+    let reader: Box<dyn Read> = match path.extension() {
+        Some(extension) if extension == "gz" => Box::new(GzDecoder::new(file)),
+        _ => Box::new(file),
+    };
+    let mut current: Option<LogEntry> = None;
+    for line in BufReader::new(reader).lines() {
+        let line = line?;
+        match (LogEntry::parse(&line), &mut current) {
+            (Some(entry), _) => {
+                if let Some(entry) = current.replace(entry)
+                    && !f(entry)
+                {
+                    return Ok(false);
+                }
+            }
+            (None, Some(entry)) => {
+                entry.text.push('\n');
+                entry.text.push_str(&line);
+            }
+            (None, None) => {}
+        }
+    }
+    Ok(current.is_none_or(f))
+}
+
+/// RFC3339, also with the space a `+` decodes to when left unescaped in a query string.
+fn offset_time<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<DateTime<FixedOffset>>, D::Error> {
+    let Some(time) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    let time = match time.len().checked_sub(6) {
+        Some(i) if time.as_bytes()[i] == b' ' => format!("{}+{}", &time[..i], &time[i + 1..]),
+        _ => time,
+    };
+    DateTime::parse_from_rfc3339(&time)
+        .map(Some)
+        .map_err(de::Error::custom)
+}
+
+#[test]
+fn test() -> Result<()> {
+    let directory = std::env::temp_dir().join("common_x_log_query_test");
+    std::fs::remove_dir_all(&directory).ok();
+    std::fs::create_dir_all(&directory)?;
+    let config = LogConfig {
+        rolling_file: Some(RollingFileConfig {
+            directory: directory.to_string_lossy().into_owned(),
+            prefix: "app".to_owned(),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    std::fs::write(
+        directory.join("app.2026-01-01"),
+        "2026-01-01T10:00:00.000Z  INFO ThreadId(01) app::db: connected\n\
+         2026-01-01T10:00:01.000Z ERROR ThreadId(02) span: app::http: failed\n  \
+         caused by: timeout\n\
+         2026-01-01T10:00:02.000Z ERROR ThreadId(03) app::db: error: timeout\n",
+    )?;
+    std::fs::write(
+        directory.join("app.2026-01-02"),
+        r#"{"timestamp":"2026-01-02T08:00:00.000+08:00","level":"WARN","target":"app::db","fields":{}}"#,
+    )?;
+
+    let reader = LogReader::new(&config)?;
+    assert_eq!(reader.files().len(), 2);
+    assert_eq!(reader.query(&LogQuery::default())?.len(), 4);
+
+    let errors = reader.query(&LogQuery {
+        level: Some("warn".to_owned()),
+        target: Some("app::http".to_owned()),
+        ..Default::default()
+    })?;
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].text.ends_with("caused by: timeout"));
+
+    let db = reader.query(&LogQuery {
+        since: Some(DateTime::parse_from_rfc3339("2026-01-01T10:00:00.500Z")?),
+        target: Some("app::db".to_owned()),
+        ..Default::default()
+    })?;
+    assert_eq!(db.len(), 2);
+    assert!(db[0].text.ends_with("error: timeout"));
+    assert_eq!(db[1].level, Some(Level::WARN));
+
+    let last = reader.query(&LogQuery {
+        tail: Some(3),
+        ..Default::default()
+    })?;
+    assert_eq!(last.len(), 3);
+    assert_eq!(last[0].target.as_deref(), Some("app::http"));
+    assert_eq!(last[2].level, Some(Level::WARN));
+    // the newest file alone is enough, the older one is not read
+    std::fs::write(directory.join("app.2026-01-01"), [0xff])?;
+    let last = reader.query(&LogQuery {
+        tail: Some(1),
+        ..Default::default()
+    })?;
+    assert_eq!(last[0].level, Some(Level::WARN));
+
+    let query: LogQuery = serde_json::from_str(r#"{"since": "2026-01-02T08:00:00 08:00"}"#)?;
+    assert_eq!(
+        query.since,
+        Some(DateTime::parse_from_rfc3339("2026-01-02T00:00:00Z")?)
+    );
+    assert!(
+        reader
+            .query(&LogQuery {
+                level: Some("loud".to_owned()),
+                ..Default::default()
+            })
+            .is_err()
+    );
+    std::fs::remove_dir_all(&directory)?;
+    Ok(())
+}
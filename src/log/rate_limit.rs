@@ -0,0 +1,236 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::{
+    Event, Metadata, Subscriber,
+    callsite::Identifier,
+    dispatcher::WeakDispatch,
+    field::{Field, Visit},
+    warn,
+};
+use tracing_subscriber::{Layer, layer::Context};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RateLimitKey {
+    /// each `info!`/`warn!`/... call site is limited on its own
+    #[default]
+    Callsite,
+    /// events with the same message are limited together, wherever they come from
+    Message,
+}
+
+/// Drops repeated events beyond `max_events` per `period`, for every sink.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub key: RateLimitKey,
+    pub max_events: u64,
+    /// milliseconds
+    pub period: u64,
+    /// seconds between warnings counting the dropped events
+    pub summary_interval: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            key: Default::default(),
+            max_events: 10,
+            period: 1000,
+            summary_interval: 10,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum Key {
+    Callsite(Identifier),
+    Message(String),
+}
+
+struct Window {
+    /// of the first event, names callsite windows in summaries
+    metadata: &'static Metadata<'static>,
+    start: Instant,
+    count: u64,
+    suppressed: u64,
+}
+
+#[derive(Clone)]
+pub(crate) struct RateLimit {
+    config: RateLimitConfig,
+    windows: Arc<Mutex<HashMap<Key, Window>>>,
+}
+
+impl RateLimit {
+    pub(crate) fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            config: config.clone(),
+            windows: Default::default(),
+        }
+    }
+
+    /// Warns about dropped events every `summary_interval` until `dispatch` is gone.
+    pub(crate) fn spawn_summary(&self, dispatch: WeakDispatch) {
+        let rate_limit = self.clone();
+        let interval = Duration::from_secs(self.config.summary_interval.max(1));
+        std::thread::spawn(move || {
+            loop {
+                std::thread::sleep(interval);
+                let Some(dispatch) = dispatch.upgrade() else {
+                    break;
+                };
+                tracing::dispatcher::with_default(&dispatch, || rate_limit.summarize());
+            }
+        });
+    }
+
+    pub(crate) fn summarize(&self) {
+        let period = Duration::from_millis(self.config.period);
+        let mut suppressed = Vec::new();
+        self.windows.lock().unwrap().retain(|key, window| {
+            if window.suppressed > 0 {
+                let name = match key {
+                    Key::Callsite(_) => {
+                        format!("{} {}", window.metadata.target(), window.metadata.name())
+                    }
+                    Key::Message(message) => message.clone(),
+                };
+                suppressed.push((name, window.suppressed));
+                window.suppressed = 0;
+            }
+            window.start.elapsed() < period
+        });
+        for (name, count) in suppressed {
+            warn!(key = %name, suppressed = count, "rate limited log events");
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for RateLimit {
+    fn event_enabled(&self, event: &Event<'_>, _ctx: Context<'_, S>) -> bool {
+        let metadata = event.metadata();
+        if metadata.target() == module_path!() {
+            return true;
+        }
+        let key = match self.config.key {
+            RateLimitKey::Callsite => Key::Callsite(metadata.callsite()),
+            RateLimitKey::Message => {
+                let mut visitor = MessageVisitor::default();
+                event.record(&mut visitor);
+                Key::Message(visitor.0)
+            }
+        };
+
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        let window = windows.entry(key).or_insert_with(|| Window {
+            metadata,
+            start: now,
+            count: 0,
+            suppressed: 0,
+        });
+        if now.duration_since(window.start) >= Duration::from_millis(self.config.period) {
+            window.start = now;
+            window.count = 0;
+        }
+        window.count += 1;
+        if window.count > self.config.max_events {
+            window.suppressed += 1;
+            return false;
+        }
+        true
+    }
+}
+
+#[derive(Default)]
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.0 = value.to_owned();
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{value:?}");
+        }
+    }
+}
+
+#[test]
+fn test() {
+    use tracing::Level;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::LogCapture;
+
+    let rate_limit = RateLimit::new(&RateLimitConfig {
+        key: RateLimitKey::Message,
+        max_events: 2,
+        period: 60_000,
+        ..Default::default()
+    });
+    let capture = LogCapture::new();
+    let subscriber = tracing_subscriber::registry()
+        .with(rate_limit.clone())
+        .with(capture.clone());
+    tracing::subscriber::with_default(subscriber, || {
+        for i in 0..5 {
+            tracing::error!(target: "app", "connection lost");
+            tracing::error!(target: "app", "retry {i}");
+        }
+        rate_limit.summarize();
+    });
+
+    let events = capture.events();
+    assert_eq!(
+        events
+            .iter()
+            .filter(|e| e.message == "connection lost")
+            .count(),
+        2
+    );
+    assert_eq!(
+        events
+            .iter()
+            .filter(|e| e.message.starts_with("retry"))
+            .count(),
+        5
+    );
+    assert!(capture.contains(Level::WARN, "rate limited log events"));
+    let summary = events.last().unwrap();
+    assert_eq!(summary.fields["key"], "connection lost");
+    assert_eq!(summary.fields["suppressed"], 3);
+
+    let rate_limit = RateLimit::new(&RateLimitConfig {
+        max_events: 1,
+        period: 60_000,
+        ..Default::default()
+    });
+    let capture = LogCapture::new();
+    let subscriber = tracing_subscriber::registry()
+        .with(rate_limit.clone())
+        .with(capture.clone());
+    tracing::subscriber::with_default(subscriber, || {
+        for i in 0..3 {
+            tracing::error!(target: "app", "retry {i}");
+        }
+        rate_limit.summarize();
+    });
+    let summary = capture.events().pop().unwrap();
+    assert!(
+        summary.fields["key"]
+            .as_str()
+            .unwrap()
+            .starts_with("app event ")
+    );
+    assert_eq!(summary.fields["suppressed"], 2);
+}
@@ -0,0 +1,523 @@
+use std::{any::TypeId, borrow::Cow, cell::RefCell, error::Error, fmt, sync::Arc};
+
+use color_eyre::Result;
+use regex::Regex;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::{Map, Value};
+use tracing::{
+    Event, Metadata, Subscriber,
+    callsite::Identifier,
+    field::{Field, Visit},
+    span,
+    subscriber::Interest,
+};
+use tracing_subscriber::{
+    Layer,
+    field::{MakeVisitor, RecordFields, VisitFmt, VisitOutput},
+    fmt::{FormatFields, FormattedFields, format::Writer},
+    layer::Context,
+};
+
+use super::format::JsonVisitor;
+
+pub(crate) const MASK: &str = "***";
+
+/// Masks values before any sink writes them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RedactConfig {
+    /// field names masked whole, also as `name: value`/`name=value` inside other values
+    pub fields: Vec<String>,
+    /// regexes masked wherever they match, e.g. tokens and emails
+    pub patterns: Vec<String>,
+}
+
+impl Default for RedactConfig {
+    fn default() -> Self {
+        Self {
+            fields: vec![
+                "password".to_owned(),
+                "secret".to_owned(),
+                "token".to_owned(),
+            ],
+            patterns: Default::default(),
+        }
+    }
+}
+
+/// Value whose `Debug` and `Display` print `***`, use `expose` to get it back.
+///
+/// Serialized as `***` too, deserialized from the plain value.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(transparent)]
+pub struct Secret<T>(T);
+
+impl<T> Secret<T> {
+    pub const fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    pub const fn expose(&self) -> &T {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(MASK)
+    }
+}
+
+impl<T> fmt::Display for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(MASK)
+    }
+}
+
+impl<T> Serialize for Secret<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(MASK)
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Redactor {
+    fields: Vec<String>,
+    /// with their replacement
+    patterns: Vec<(Regex, String)>,
+}
+
+impl Redactor {
+    pub(crate) fn new(config: &RedactConfig) -> Result<Self> {
+        let mut patterns = Vec::new();
+        for name in &config.fields {
+            let regex = format!(
+                r#"(?i)\b({})(\s*[:=]\s*)(?:"(?:[^"\\]|\\.)*"|[^\s,;}})\]]+)"#,
+                regex::escape(name)
+            );
+            patterns.push((Regex::new(&regex)?, format!("${{1}}${{2}}{MASK}")));
+        }
+        for pattern in &config.patterns {
+            patterns.push((Regex::new(pattern)?, MASK.to_owned()));
+        }
+        Ok(Self {
+            fields: config.fields.clone(),
+            patterns,
+        })
+    }
+
+    fn masks(&self, field: &Field) -> bool {
+        self.masks_name(field.name())
+    }
+
+    pub(crate) fn masks_name(&self, name: &str) -> bool {
+        self.fields.iter().any(|f| f.eq_ignore_ascii_case(name))
+    }
+
+    pub(crate) fn redact<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        let mut value = Cow::Borrowed(value);
+        for (regex, replacement) in &self.patterns {
+            if let Cow::Owned(replaced) = regex.replace_all(&value, replacement.as_str()) {
+                value = Cow::Owned(replaced);
+            }
+        }
+        value
+    }
+}
+
+/// Visitor passing redacted values on to `inner`.
+pub(crate) struct Redact<V> {
+    pub(crate) inner: V,
+    redactor: Arc<Redactor>,
+}
+
+impl<V> Redact<V> {
+    pub(crate) const fn new(inner: V, redactor: Arc<Redactor>) -> Self {
+        Self { inner, redactor }
+    }
+}
+
+impl<V: Visit> Visit for Redact<V> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        match self.redactor.masks(field) {
+            true => self.inner.record_debug(field, &format_args!("{MASK}")),
+            false => self.inner.record_f64(field, value),
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        match self.redactor.masks(field) {
+            true => self.inner.record_debug(field, &format_args!("{MASK}")),
+            false => self.inner.record_i64(field, value),
+        }
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        match self.redactor.masks(field) {
+            true => self.inner.record_debug(field, &format_args!("{MASK}")),
+            false => self.inner.record_u64(field, value),
+        }
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        match self.redactor.masks(field) {
+            true => self.inner.record_debug(field, &format_args!("{MASK}")),
+            false => self.inner.record_bool(field, value),
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match scanned(field).unwrap_or_else(|| Scanned::of(&self.redactor, field, value)) {
+            Scanned::Masked => self.inner.record_debug(field, &format_args!("{MASK}")),
+            Scanned::Replaced(value) => self.inner.record_str(field, &value),
+            Scanned::Unchanged => self.inner.record_str(field, value),
+        }
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn Error + 'static)) {
+        match scanned(field)
+            .unwrap_or_else(|| Scanned::of(&self.redactor, field, &value.to_string()))
+        {
+            Scanned::Masked => self.inner.record_debug(field, &format_args!("{MASK}")),
+            Scanned::Replaced(value) => self.inner.record_debug(field, &format_args!("{value}")),
+            Scanned::Unchanged => self.inner.record_error(field, value),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        match scanned(field)
+            .unwrap_or_else(|| Scanned::of(&self.redactor, field, &format!("{value:?}")))
+        {
+            Scanned::Masked => self.inner.record_debug(field, &format_args!("{MASK}")),
+            Scanned::Replaced(value) => self.inner.record_debug(field, &format_args!("{value}")),
+            Scanned::Unchanged => self.inner.record_debug(field, value),
+        }
+    }
+}
+
+impl<V: VisitOutput<fmt::Result>> VisitOutput<fmt::Result> for Redact<V> {
+    fn finish(self) -> fmt::Result {
+        self.inner.finish()
+    }
+}
+
+impl<V: VisitFmt> VisitFmt for Redact<V> {
+    fn writer(&mut self) -> &mut dyn fmt::Write {
+        self.inner.writer()
+    }
+}
+
+/// Redaction of a formatted field value.
+#[derive(Clone)]
+enum Scanned {
+    Masked,
+    Replaced(String),
+    Unchanged,
+}
+
+impl Scanned {
+    fn of(redactor: &Redactor, field: &Field, value: &str) -> Self {
+        if redactor.masks(field) {
+            return Self::Masked;
+        }
+        match redactor.redact(value) {
+            Cow::Owned(value) => Self::Replaced(value),
+            Cow::Borrowed(_) => Self::Unchanged,
+        }
+    }
+}
+
+/// Scanned fields of an event, by name.
+type ScannedEvent = (Identifier, Vec<(&'static str, Scanned)>);
+
+thread_local! {
+    /// Events being passed to the sinks, innermost last.
+    static SCANNED: RefCell<Vec<ScannedEvent>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The redaction of `field` if it belongs to the event being passed to the sinks.
+fn scanned(field: &Field) -> Option<Scanned> {
+    SCANNED.with_borrow(|events| {
+        let (callsite, fields) = events.last()?;
+        if *callsite != field.callsite() {
+            return None;
+        }
+        fields
+            .iter()
+            .find(|(name, _)| *name == field.name())
+            .map(|(_, scanned)| scanned.clone())
+    })
+}
+
+/// Collects the redaction of every string, error and `Debug` field.
+struct Scan<'a> {
+    redactor: &'a Redactor,
+    fields: Vec<(&'static str, Scanned)>,
+}
+
+impl Visit for Scan<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        let scanned = Scanned::of(self.redactor, field, value);
+        self.fields.push((field.name(), scanned));
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn Error + 'static)) {
+        let scanned = Scanned::of(self.redactor, field, &value.to_string());
+        self.fields.push((field.name(), scanned));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        let scanned = Scanned::of(self.redactor, field, &format!("{value:?}"));
+        self.fields.push((field.name(), scanned));
+    }
+}
+
+/// Pops the scan of an event once the sinks are done with it, even on panic.
+struct ScanGuard;
+
+impl Drop for ScanGuard {
+    fn drop(&mut self) {
+        SCANNED.with_borrow_mut(|events| events.pop());
+    }
+}
+
+/// Wraps the sinks so that each event is formatted and scanned once, not once per sink.
+pub(crate) struct RedactScope<L> {
+    inner: L,
+    redactor: Arc<Redactor>,
+}
+
+impl<L> RedactScope<L> {
+    pub(crate) const fn new(inner: L, redactor: Arc<Redactor>) -> Self {
+        Self { inner, redactor }
+    }
+}
+
+impl<S: Subscriber, L: Layer<S>> Layer<S> for RedactScope<L> {
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut scan = Scan {
+            redactor: &self.redactor,
+            fields: Vec::new(),
+        };
+        event.record(&mut scan);
+        SCANNED.with_borrow_mut(|events| events.push((event.metadata().callsite(), scan.fields)));
+        let _guard = ScanGuard;
+        self.inner.on_event(event, ctx);
+    }
+
+    fn on_register_dispatch(&self, subscriber: &tracing::Dispatch) {
+        self.inner.on_register_dispatch(subscriber);
+    }
+
+    fn on_layer(&mut self, subscriber: &mut S) {
+        self.inner.on_layer(subscriber);
+    }
+
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        self.inner.register_callsite(metadata)
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, ctx: Context<'_, S>) -> bool {
+        self.inner.enabled(metadata, ctx)
+    }
+
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_new_span(attrs, id, ctx);
+    }
+
+    fn on_record(&self, span: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        self.inner.on_record(span, values, ctx);
+    }
+
+    fn on_follows_from(&self, span: &span::Id, follows: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_follows_from(span, follows, ctx);
+    }
+
+    fn event_enabled(&self, event: &Event<'_>, ctx: Context<'_, S>) -> bool {
+        self.inner.event_enabled(event, ctx)
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_enter(id, ctx);
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_exit(id, ctx);
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        self.inner.on_close(id, ctx);
+    }
+
+    fn on_id_change(&self, old: &span::Id, new: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_id_change(old, new, ctx);
+    }
+
+    unsafe fn downcast_raw(&self, id: TypeId) -> Option<*const ()> {
+        if id == TypeId::of::<Self>() {
+            return Some(self as *const Self as *const ());
+        }
+        // SAFETY: forwarded as is, like `Layered` does for its parts
+        unsafe { self.inner.downcast_raw(id) }
+    }
+}
+
+/// Text field formatter `M` with redaction.
+pub(crate) struct RedactFields<M> {
+    inner: M,
+    redactor: Arc<Redactor>,
+}
+
+impl<M> RedactFields<M> {
+    pub(crate) const fn new(inner: M, redactor: Arc<Redactor>) -> Self {
+        Self { inner, redactor }
+    }
+}
+
+impl<'a, M: MakeVisitor<Writer<'a>>> MakeVisitor<Writer<'a>> for RedactFields<M> {
+    type Visitor = Redact<M::Visitor>;
+
+    fn make_visitor(&self, target: Writer<'a>) -> Self::Visitor {
+        Redact::new(self.inner.make_visitor(target), self.redactor.clone())
+    }
+}
+
+/// JSON field formatter with redaction, keeping span fields a single object.
+pub(crate) struct JsonFields {
+    redactor: Arc<Redactor>,
+}
+
+impl JsonFields {
+    pub(crate) const fn new(redactor: Arc<Redactor>) -> Self {
+        Self { redactor }
+    }
+
+    fn write(&self, fields: Map<String, Value>, record: impl FnOnce(&mut dyn Visit)) -> String {
+        let mut visitor = Redact::new(JsonVisitor(fields), self.redactor.clone());
+        record(&mut visitor);
+        Value::Object(visitor.inner.0).to_string()
+    }
+}
+
+impl<'a> FormatFields<'a> for JsonFields {
+    fn format_fields<R: RecordFields>(&self, mut writer: Writer<'a>, fields: R) -> fmt::Result {
+        writer.write_str(&self.write(Map::new(), |visitor| fields.record(visitor)))
+    }
+
+    fn add_fields(
+        &self,
+        current: &'a mut FormattedFields<Self>,
+        fields: &tracing::span::Record<'_>,
+    ) -> fmt::Result {
+        let map = match current.is_empty() {
+            true => Map::new(),
+            false => serde_json::from_str(current).map_err(|_| fmt::Error)?,
+        };
+        current.fields = self.write(map, |visitor| fields.record(visitor));
+        Ok(())
+    }
+}
+
+#[test]
+fn test() -> Result<()> {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use crate::test_util::Buffer;
+
+    #[allow(dead_code)]
+    #[derive(Debug)]
+    struct Config {
+        username: String,
+        password: String,
+        key: Secret<String>,
+    }
+
+    let redactor = Arc::new(Redactor::new(&RedactConfig {
+        patterns: vec![r"[\w.+-]+@[\w-]+\.[\w.]+".to_owned()],
+        ..Default::default()
+    })?);
+    let buffer = Buffer::default();
+    let writer = buffer.clone();
+    let subscriber = tracing_subscriber::registry().with(
+        tracing_subscriber::fmt::layer()
+            .with_ansi(false)
+            .fmt_fields(RedactFields::new(
+                tracing_subscriber::fmt::format::DefaultFields::new(),
+                redactor,
+            ))
+            .with_writer(move || writer.clone()),
+    );
+    tracing::subscriber::with_default(subscriber, || {
+        let config = Config {
+            username: "jl".to_owned(),
+            password: "hunter2".to_owned(),
+            key: Secret::new("k3y".to_owned()),
+        };
+        tracing::info!(token = "abc", ?config, "sent to jl@example.com");
+    });
+
+    let output = buffer.contents();
+    for secret in ["abc", "hunter2", "k3y", "jl@example.com"] {
+        assert!(!output.contains(secret), "{secret} in {output}");
+    }
+    assert!(output.contains("token=***") && output.contains(r#"username: "jl""#));
+    assert!(output.contains("password: ***") && output.contains("sent to ***"));
+
+    // formatted and scanned once for both sinks
+    struct Counted(Arc<std::sync::atomic::AtomicUsize>);
+    impl fmt::Debug for Counted {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            f.write_str("token=abc")
+        }
+    }
+    let redactor = Arc::new(Redactor::new(&RedactConfig::default())?);
+    let sink = |buffer: &Buffer| {
+        let writer = buffer.clone();
+        tracing_subscriber::fmt::layer()
+            .with_ansi(false)
+            .fmt_fields(RedactFields::new(
+                tracing_subscriber::fmt::format::DefaultFields::new(),
+                redactor.clone(),
+            ))
+            .with_writer(move || writer.clone())
+            .boxed()
+    };
+    let buffers = [Buffer::default(), Buffer::default()];
+    let subscriber = tracing_subscriber::registry().with(RedactScope::new(
+        buffers.iter().map(sink).collect::<Vec<_>>(),
+        redactor.clone(),
+    ));
+    let count = Arc::default();
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(value = ?Counted(Arc::clone(&count)), "scanned");
+    });
+    assert_eq!(count.load(std::sync::atomic::Ordering::Relaxed), 1);
+    assert!(
+        buffers
+            .iter()
+            .all(|b| b.contents().contains("value=token=***"))
+    );
+
+    let key: Secret<String> = serde_json::from_str(r#""k3y""#)?;
+    assert_eq!(key.expose(), "k3y");
+    assert_eq!(serde_json::to_string(&key)?, r#""***""#);
+    assert!(
+        Redactor::new(&RedactConfig {
+            patterns: vec!["[".to_owned()],
+            ..Default::default()
+        })
+        .is_err()
+    );
+    Ok(())
+}
@@ -0,0 +1,325 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    thread::{self, JoinHandle},
+};
+
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+use flate2::{Compression, write::GzEncoder};
+use serde::{
+    Deserialize, Deserializer, Serialize, Serializer,
+    de::{self, MapAccess, SeqAccess, Visitor, value::MapAccessDeserializer},
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Rotation {
+    Never,
+    Hourly,
+    #[default]
+    Daily,
+    /// bytes
+    Size(u64),
+}
+
+/// Files are named `{prefix}.{stamp}.{suffix}`, the stamp being UTC and
+/// absent with `Rotation::Never`. Size rotated stamps end in a sequence number
+/// for files rotated within the same millisecond.
+///
+/// Also read from the former `(directory, prefix)` tuple.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(remote = "Self", default)]
+pub struct RollingFileConfig {
+    pub directory: String,
+    pub prefix: String,
+    pub suffix: Option<String>,
+    pub rotation: Rotation,
+    /// oldest files beyond this are deleted on rotation
+    pub max_files: Option<usize>,
+    /// gzip files once rotated out, off the logging thread
+    pub compress: bool,
+}
+
+impl Default for RollingFileConfig {
+    fn default() -> Self {
+        Self {
+            directory: "logs".to_owned(),
+            prefix: "log".to_owned(),
+            suffix: None,
+            rotation: Default::default(),
+            max_files: None,
+            compress: false,
+        }
+    }
+}
+
+impl Serialize for RollingFileConfig {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Self::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RollingFileConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Compat;
+
+        impl<'de> Visitor<'de> for Compat {
+            type Value = RollingFileConfig;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str("struct RollingFileConfig or a (directory, prefix) tuple")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let directory = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let prefix = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                Ok(RollingFileConfig {
+                    directory,
+                    prefix,
+                    ..Default::default()
+                })
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                RollingFileConfig::deserialize(MapAccessDeserializer::new(map))
+            }
+        }
+
+        deserializer.deserialize_any(Compat)
+    }
+}
+
+impl RollingFileConfig {
+    fn file_name(&self, now: DateTime<Utc>, seq: u32) -> String {
+        let stamp = match self.rotation {
+            Rotation::Never => None,
+            Rotation::Hourly => Some(now.format("%Y-%m-%d-%H").to_string()),
+            Rotation::Daily => Some(now.format("%Y-%m-%d").to_string()),
+            Rotation::Size(_) => Some(format!("{}-{seq:04}", now.format("%Y-%m-%d-%H%M%S%3f"))),
+        };
+        let mut name = self.prefix.clone();
+        if let Some(stamp) = stamp {
+            name += &format!(".{stamp}");
+        }
+        if let Some(suffix) = &self.suffix {
+            name += &format!(".{suffix}");
+        }
+        name
+    }
+
+    fn next_rotation(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let period = match self.rotation {
+            Rotation::Hourly => TimeDelta::hours(1),
+            Rotation::Daily => TimeDelta::days(1),
+            Rotation::Never | Rotation::Size(_) => return None,
+        };
+        now.duration_trunc(period).ok().map(|t| t + period)
+    }
+
+    fn is_log_file(&self, name: &str) -> bool {
+        let name = name.strip_suffix(".gz").unwrap_or(name);
+        let name = match &self.suffix {
+            Some(suffix) => match name
+                .strip_suffix(suffix.as_str())
+                .and_then(|name| name.strip_suffix('.'))
+            {
+                Some(name) => name,
+                None => return false,
+            },
+            None => name,
+        };
+        name == self.prefix
+            || name
+                .strip_prefix(self.prefix.as_str())
+                .is_some_and(|stamp| stamp.starts_with('.') && !stamp[1..].contains('.'))
+    }
+
+    /// Log files of this config, oldest first, compressed ones included.
+    pub fn files(&self) -> io::Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.directory)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file()
+                && self.is_log_file(&entry.file_name().to_string_lossy())
+            {
+                files.push(entry.path());
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// Deletes the oldest files beyond `max_files`, never `current`.
+    fn prune(&self, current: &Path) -> io::Result<()> {
+        let Some(max_files) = self.max_files else {
+            return Ok(());
+        };
+        let files = self.files()?;
+        for path in files
+            .iter()
+            .take(files.len().saturating_sub(max_files.max(1)))
+        {
+            if path != current {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) struct RollingWriter {
+    config: RollingFileConfig,
+    file: File,
+    path: PathBuf,
+    seq: u32,
+    size: u64,
+    next_rotation: Option<DateTime<Utc>>,
+    /// compression and pruning after the last rotation
+    cleanup: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl RollingWriter {
+    pub(crate) fn new(config: RollingFileConfig) -> io::Result<Self> {
+        let writer = Self::open(config, Utc::now(), 0)?;
+        writer.config.prune(&writer.path)?;
+        Ok(writer)
+    }
+
+    fn open(config: RollingFileConfig, now: DateTime<Utc>, seq: u32) -> io::Result<Self> {
+        fs::create_dir_all(&config.directory)?;
+        let path = Path::new(&config.directory).join(config.file_name(now, seq));
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(Self {
+            size: file.metadata()?.len(),
+            next_rotation: config.next_rotation(now),
+            config,
+            file,
+            path,
+            seq,
+            cleanup: None,
+        })
+    }
+
+    fn should_rotate(&self, len: usize) -> bool {
+        match self.config.rotation {
+            Rotation::Size(max) => self.size > 0 && self.size + len as u64 > max,
+            _ => self.next_rotation.is_some_and(|t| Utc::now() >= t),
+        }
+    }
+
+    /// Errors of the previous cleanup surface here, on the next rotation.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.join_cleanup()?;
+        let now = Utc::now();
+        let seq = if self.path.ends_with(self.config.file_name(now, self.seq)) {
+            self.seq + 1
+        } else {
+            0
+        };
+        let old = std::mem::replace(self, Self::open(self.config.clone(), now, seq)?)
+            .path
+            .clone();
+        let compress_old = self.path != old && self.config.compress;
+        let (config, current) = (self.config.clone(), self.path.clone());
+        self.cleanup = Some(thread::spawn(move || {
+            if compress_old {
+                compress(&old)?;
+            }
+            config.prune(&current)
+        }));
+        Ok(())
+    }
+
+    fn join_cleanup(&mut self) -> io::Result<()> {
+        match self.cleanup.take() {
+            Some(cleanup) => cleanup
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("log file cleanup panicked"))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for RollingWriter {
+    fn drop(&mut self) {
+        self.join_cleanup().ok();
+    }
+}
+
+impl Write for RollingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.should_rotate(buf.len()) {
+            self.rotate()?;
+        }
+        let n = self.file.write(buf)?;
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn compress(path: &Path) -> io::Result<()> {
+    let mut gz_path = path.as_os_str().to_owned();
+    gz_path.push(".gz");
+    let mut encoder = GzEncoder::new(File::create(gz_path)?, Compression::default());
+    io::copy(&mut File::open(path)?, &mut encoder)?;
+    encoder.finish()?.sync_all()?;
+    fs::remove_file(path)
+}
+
+#[test]
+fn test() -> io::Result<()> {
+    let directory = std::env::temp_dir().join("common_x_log_rolling_test");
+    fs::remove_dir_all(&directory).ok();
+    let config = RollingFileConfig {
+        directory: directory.to_string_lossy().into_owned(),
+        prefix: "app".to_owned(),
+        suffix: Some("log".to_owned()),
+        rotation: Rotation::Size(10),
+        max_files: Some(3),
+        compress: true,
+    };
+    let mut writer = RollingWriter::new(config.clone())?;
+    for line in ["line 0\n", "line 1\n", "line 2\n", "line 3\n", "line 4\n"] {
+        writer.write_all(line.as_bytes())?;
+    }
+    // waits for the last compression
+    drop(writer);
+    fs::write(directory.join("app-other.log"), "")?;
+
+    let files = config.files()?;
+    assert_eq!(files.len(), 3);
+    assert!(files[..2].iter().all(|f| f.extension().unwrap() == "gz"));
+    assert_eq!(fs::read_to_string(&files[2])?, "line 4\n");
+    fs::remove_dir_all(&directory)?;
+
+    let old: super::LogConfig =
+        ron::from_str(r#"(rolling_file: Some(("logs", "app")))"#).map_err(io::Error::other)?;
+    let old = old.rolling_file.unwrap();
+    assert_eq!(
+        (old.directory.as_str(), old.prefix.as_str()),
+        ("logs", "app")
+    );
+    let ron = ron::to_string(&config).map_err(io::Error::other)?;
+    assert_eq!(
+        ron::from_str::<RollingFileConfig>(&ron).map_err(io::Error::other)?,
+        config
+    );
+    let json = serde_json::to_string(&config)?;
+    assert_eq!(serde_json::from_str::<RollingFileConfig>(&json)?, config);
+    let typo = serde_json::from_str::<RollingFileConfig>(r#"{"rotation": "Weekly"}"#);
+    assert!(
+        typo.unwrap_err()
+            .to_string()
+            .contains("unknown variant `Weekly`")
+    );
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use std::{fmt, time::Instant};
+
+use chrono::{
+    DateTime, FixedOffset, Local, SecondsFormat, TimeZone, Utc,
+    format::{Item, StrftimeItems},
+};
+use color_eyre::{Result, eyre::bail};
+use serde::{Deserialize, Serialize};
+use tracing_subscriber::fmt::{format::Writer, time::FormatTime};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Timezone {
+    Utc,
+    #[default]
+    Local,
+    /// e.g. `+08:00`
+    Fixed(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimeFormat {
+    /// with milliseconds, `Z` for UTC
+    #[default]
+    Rfc3339,
+    /// strftime pattern, e.g. `%Y-%m-%d %T%.3f`
+    Custom(String),
+    /// seconds since the subscriber was built
+    Uptime,
+}
+
+/// Timestamp of every sink.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimeConfig {
+    pub timezone: Timezone,
+    pub format: TimeFormat,
+}
+
+#[derive(Clone, Copy)]
+enum Zone {
+    Utc,
+    Local,
+    Fixed(FixedOffset),
+}
+
+#[derive(Clone)]
+pub(crate) struct Timer {
+    start: Instant,
+    zone: Zone,
+    format: TimeFormat,
+}
+
+impl Timer {
+    pub(crate) fn new(config: &TimeConfig) -> Result<Self> {
+        let zone = match &config.timezone {
+            Timezone::Utc => Zone::Utc,
+            Timezone::Local => Zone::Local,
+            Timezone::Fixed(offset) => match offset.parse() {
+                Ok(offset) => Zone::Fixed(offset),
+                Err(e) => bail!("invalid log timezone offset {offset:?}: {e}"),
+            },
+        };
+        if let TimeFormat::Custom(pattern) = &config.format
+            && StrftimeItems::new(pattern).any(|item| item == Item::Error)
+        {
+            bail!("invalid log time format {pattern:?}");
+        }
+        Ok(Self {
+            start: Instant::now(),
+            zone,
+            format: config.format.clone(),
+        })
+    }
+
+    fn write<Tz>(&self, w: &mut Writer<'_>, now: DateTime<Tz>) -> fmt::Result
+    where
+        Tz: TimeZone,
+        Tz::Offset: fmt::Display,
+    {
+        match &self.format {
+            TimeFormat::Rfc3339 => w.write_str(&now.to_rfc3339_opts(SecondsFormat::Millis, true)),
+            TimeFormat::Custom(pattern) => write!(w, "{}", now.format(pattern)),
+            TimeFormat::Uptime => write!(w, "{:.3}s", self.start.elapsed().as_secs_f64()),
+        }
+    }
+}
+
+impl FormatTime for Timer {
+    fn format_time(&self, w: &mut Writer<'_>) -> fmt::Result {
+        match self.zone {
+            Zone::Utc => self.write(w, Utc::now()),
+            Zone::Local => self.write(w, Local::now()),
+            Zone::Fixed(offset) => self.write(w, Utc::now().with_timezone(&offset)),
+        }
+    }
+}
+
+#[test]
+fn test() -> Result<()> {
+    let format = |timezone, format| -> Result<String> {
+        let mut time = String::new();
+        Timer::new(&TimeConfig { timezone, format })?.format_time(&mut Writer::new(&mut time))?;
+        Ok(time)
+    };
+
+    assert!(format(Timezone::Utc, TimeFormat::Rfc3339)?.ends_with('Z'));
+    assert!(format(Timezone::Fixed("+08:00".to_owned()), TimeFormat::Rfc3339)?.ends_with("+08:00"));
+    assert_eq!(
+        format(Timezone::Utc, TimeFormat::Custom("%Y".to_owned()))?,
+        Utc::now().format("%Y").to_string()
+    );
+    assert!(format(Timezone::Local, TimeFormat::Uptime)?.starts_with("0.0"));
+    assert!(format(Timezone::Fixed("CST".to_owned()), TimeFormat::Rfc3339).is_err());
+    assert!(format(Timezone::Utc, TimeFormat::Custom("%Q".to_owned())).is_err());
+    Ok(())
+}
@@ -1,20 +1,51 @@
-use std::time::Duration;
+mod dkim;
+mod email;
+mod error;
+mod queue;
+mod template;
+mod transport;
+
+use std::sync::Arc;
 
 use color_eyre::Result;
-use lettre::transport::smtp::authentication::Credentials;
-use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use lettre::{
+    Address, AsyncSendmailTransport, Message, Tokio1Executor,
+    message::{Mailbox, dkim::DkimConfig as Signer},
+};
 use serde::{Deserialize, Serialize};
-use tracing::{error, info};
+
+pub use dkim::DkimConfig;
+pub use email::{Attachment, Email};
+pub use error::MailError;
+pub use queue::{MailQueue, MailQueueConfig, QueuedMail};
+pub use template::TemplateRegistry;
+pub use transport::{MailTransport, MemoryTransport, TlsMode, Transport};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(default)]
 pub struct MailerConfig {
     pub username: String,
     pub password: String,
+    /// From address, `username` when empty
+    pub from: String,
+    /// shown with `from`, e.g. `Support <support@example.com>`
+    pub from_name: Option<String>,
+    /// `MAIL FROM` of the smtp envelope, e.g. a bounce address, `from` when unset
+    pub envelope_from: Option<String>,
     pub relay: String,
     pub port: u16,
-    pub tls: bool,
+    pub tls: TlsMode,
+    /// pem or der file of extra roots to trust, read like `tls::read_ca`
+    pub ca: Option<String>,
+    /// milliseconds
     pub timeout: u64,
+    /// pooled smtp connections
+    pub pool_size: u32,
+    /// milliseconds an idle pooled connection is kept
+    pub idle_timeout: u64,
+    pub transport: MailTransport,
+    /// signs every sent message when set
+    pub dkim: Option<DkimConfig>,
 }
 
 impl Default for MailerConfig {
@@ -22,68 +53,190 @@ impl Default for MailerConfig {
         Self {
             username: Default::default(),
             password: Default::default(),
+            from: Default::default(),
+            from_name: None,
+            envelope_from: None,
             relay: Default::default(),
             port: 465,
-            tls: false,
+            tls: TlsMode::Implicit,
+            ca: None,
             timeout: 5000,
+            pool_size: 10,
+            idle_timeout: 60_000,
+            transport: MailTransport::Smtp,
+            dkim: None,
         }
     }
 }
 
 #[derive(Clone)]
 pub struct Mailer {
-    address: String,
-    mailer: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    envelope_from: Option<Address>,
+    transport: Arc<dyn Transport>,
+    dkim: Option<Arc<Signer>>,
 }
 
 impl Mailer {
     pub fn new(config: &MailerConfig) -> Result<Self> {
-        let creds = Credentials::new(config.username.to_string(), config.password.to_string());
-        let builder = if config.tls {
-            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.relay)?
+        let transport: Arc<dyn Transport> = match &config.transport {
+            MailTransport::Smtp => Arc::new(transport::smtp_transport(config)?),
+            MailTransport::File(dir) => Arc::new(transport::file_transport(dir)?),
+            MailTransport::Sendmail(None) => {
+                Arc::new(AsyncSendmailTransport::<Tokio1Executor>::new())
+            }
+            MailTransport::Sendmail(Some(command)) => {
+                Arc::new(AsyncSendmailTransport::<Tokio1Executor>::new_with_command(
+                    command,
+                ))
+            }
+        };
+        let from = if config.from.is_empty() {
+            &config.username
         } else {
-            AsyncSmtpTransport::<Tokio1Executor>::relay(&config.relay)?
+            &config.from
         };
-        let mailer = builder
-            .port(config.port)
-            .credentials(creds)
-            .timeout(Some(Duration::from_millis(config.timeout)))
-            .build();
+        let mut mailer = Self::with_transport(from, transport)?;
+        if let Some(name) = &config.from_name {
+            mailer.from.name = Some(name.clone());
+        }
+        if let Some(envelope_from) = &config.envelope_from {
+            mailer.envelope_from = Some(email::address(envelope_from)?);
+        }
+        match &config.dkim {
+            Some(dkim) => mailer.with_dkim(dkim),
+            None => Ok(mailer),
+        }
+    }
 
+    /// Sends from `from`, an address or `Name <address>`, over any transport,
+    /// e.g. a `MemoryTransport` in tests.
+    pub fn with_transport(from: &str, transport: Arc<dyn Transport>) -> Result<Self> {
         Ok(Self {
-            address: config.username.to_string(),
-            mailer,
+            from: email::mailbox(from)?,
+            envelope_from: None,
+            transport,
+            dkim: None,
         })
     }
 
-    pub async fn send(&self, subject: &str, body: &str, to: &str) {
-        let email = Message::builder()
-            .from(self.address.parse().unwrap())
-            .to(to.parse().unwrap())
-            .subject(subject)
-            .body(String::from(body))
-            .unwrap();
-
-        match self.mailer.send(email).await {
-            Ok(_) => info!("Email sent successfully!"),
-            Err(e) => error!("Could not send email: {:?}", e),
+    pub fn with_dkim(mut self, config: &DkimConfig) -> Result<Self> {
+        self.dkim = Some(Arc::new(config.signer()?));
+        Ok(self)
+    }
+
+    pub async fn send(&self, subject: &str, body: &str, to: &str) -> Result<(), MailError> {
+        self.send_email(&Email::new(subject).to(to).text(body))
+            .await
+    }
+
+    pub async fn send_email(&self, email: &Email) -> Result<(), MailError> {
+        let mut message = self.message(email)?;
+        if let Some(dkim) = &self.dkim {
+            message.sign(dkim);
         }
+        self.transport.send(message).await
+    }
+
+    /// `email` as it would be sent, unsigned.
+    pub(crate) fn message(&self, email: &Email) -> Result<Message, MailError> {
+        email.build_with(&self.from, self.envelope_from.as_ref())
     }
 }
 
 #[tokio::test]
 async fn test_mailer() -> Result<()> {
+    let memory = MemoryTransport::default();
+    let mailer = Mailer::with_transport("noreply@example.com", Arc::new(memory.clone()))?;
+    mailer
+        .send("Test", "This is a test email", "name@example.com")
+        .await?;
+    let sent = mailer.send("Test", "body", "not an address").await;
+    assert!(matches!(sent, Err(MailError::Address(_))));
+    let messages = memory.messages();
+    assert_eq!(messages.len(), 1);
+    let raw = String::from_utf8(messages[0].formatted())?;
+    assert!(raw.contains("To: name@example.com") && raw.contains("This is a test email"));
+    assert!(
+        !MailError::Permanent {
+            code: 550,
+            message: "mailbox unavailable".to_string()
+        }
+        .is_retryable()
+    );
+
+    let dir = std::env::temp_dir().join("common_x_mail_file_test");
+    std::fs::remove_dir_all(&dir).ok();
     let mailer = Mailer::new(&MailerConfig {
-        username: "username".to_string(),
-        password: "password".to_string(),
-        relay: "relay".to_string(),
-        port: 587,
-        tls: false,
-        timeout: 5000,
+        username: "noreply@example.com".to_string(),
+        transport: MailTransport::File(dir.to_string_lossy().into_owned()),
+        ..Default::default()
     })?;
-    mailer
-        .send("Test", "This is a test email", "name@host")
-        .await;
+    mailer.send("Test", "saved", "name@example.com").await?;
+    let files = std::fs::read_dir(&dir)?.collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(files.len(), 1);
+    assert!(files[0].path().extension().is_some_and(|e| e == "eml"));
+    std::fs::remove_dir_all(&dir)?;
+
+    let ca = std::env::temp_dir().join("common_x_mail_ca.pem");
+    std::fs::write(&ca, crate::tls::new_ca().0.pem())?;
+    let mut config = MailerConfig {
+        from: "noreply@example.com".to_string(),
+        relay: "localhost".to_string(),
+        tls: TlsMode::StartTlsRequired,
+        ca: Some(ca.to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+    assert!(Mailer::new(&config).is_ok());
+    std::fs::remove_file(&ca)?;
+    assert!(Mailer::new(&config).is_err());
+    config.tls = TlsMode::None;
+    assert!(Mailer::new(&config).is_ok());
+    for (tls, mode) in [
+        ("true", TlsMode::StartTlsRequired),
+        ("false", TlsMode::Implicit),
+        (r#""StartTlsOpportunistic""#, TlsMode::StartTlsOpportunistic),
+    ] {
+        let old: MailerConfig = serde_json::from_str(&format!(r#"{{"tls": {tls}}}"#))?;
+        assert_eq!(old.tls, mode);
+    }
+    let ron = ron::to_string(&config)?;
+    assert_eq!(ron::from_str::<MailerConfig>(&ron)?.tls, TlsMode::None);
+    let typo = serde_json::from_str::<MailerConfig>(r#"{"tls": "StartTls"}"#);
+    assert!(
+        typo.unwrap_err()
+            .to_string()
+            .contains("expected one of `None`")
+    );
+    let typo = ron::from_str::<MailerConfig>("(tls: StartTls)");
+    assert!(typo.unwrap_err().to_string().contains("StartTls"));
 
+    config.username = "api-key-123".to_string();
+    config.from_name = Some("Support Team".to_string());
+    config.envelope_from = Some("bounce@example.com".to_string());
+    let mailer = Mailer::new(&config)?;
+    let email = Email::new("Test").to("name@example.com");
+    let message = mailer.message(&email)?;
+    let raw = String::from_utf8(message.formatted())?;
+    assert!(raw.contains("From: \"Support Team\" <noreply@example.com>"));
+    assert_eq!(
+        message
+            .envelope()
+            .from()
+            .map(ToString::to_string)
+            .as_deref(),
+        Some("bounce@example.com")
+    );
+    let raw = String::from_utf8(
+        mailer
+            .message(&email.from("other@example.com"))?
+            .formatted(),
+    )?;
+    assert!(raw.contains("From: other@example.com"));
+    config.from.clear();
+    assert!(Mailer::new(&config).is_err());
+    config.from = "noreply@example.com".to_string();
+    config.envelope_from = Some("bounce".to_string());
+    assert!(Mailer::new(&config).is_err());
     Ok(())
 }
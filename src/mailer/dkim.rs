@@ -0,0 +1,95 @@
+use color_eyre::{Result, eyre::eyre};
+use lettre::message::{
+    dkim::{self, DkimCanonicalization, DkimSigningAlgorithm, DkimSigningKey},
+    header::HeaderName,
+};
+use rsa::{
+    RsaPrivateKey,
+    pkcs1::{DecodeRsaPrivateKey, EncodeRsaPrivateKey, LineEnding},
+    pkcs8::DecodePrivateKey,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::tls::read_key;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct DkimConfig {
+    /// published as `{selector}._domainkey.{domain}`
+    pub selector: String,
+    pub domain: String,
+    /// rsa private key file, pem or der, read with `tls::read_key`
+    pub key: String,
+    /// header names covered by the signature
+    pub headers: Vec<String>,
+}
+
+impl Default for DkimConfig {
+    fn default() -> Self {
+        Self {
+            selector: Default::default(),
+            domain: Default::default(),
+            key: Default::default(),
+            headers: ["From", "To", "Subject", "Date"].map(String::from).to_vec(),
+        }
+    }
+}
+
+impl DkimConfig {
+    pub(crate) fn signer(&self) -> Result<dkim::DkimConfig> {
+        let key = read_key(self.key.clone())?;
+        let der = key.secret_der();
+        let key = RsaPrivateKey::from_pkcs8_der(der)
+            .or_else(|_| RsaPrivateKey::from_pkcs1_der(der))
+            .map_err(|e| eyre!("invalid dkim key {}: {e}", self.key))?
+            .to_pkcs1_pem(LineEnding::LF)?;
+        let key = DkimSigningKey::new(&key, DkimSigningAlgorithm::Rsa)
+            .map_err(|e| eyre!("invalid dkim key {}: {e}", self.key))?;
+        let headers = self
+            .headers
+            .iter()
+            .map(|name| {
+                HeaderName::new_from_ascii(name.clone())
+                    .map_err(|_| eyre!("invalid dkim header name: {name}"))
+            })
+            .collect::<Result<_>>()?;
+        Ok(dkim::DkimConfig::new(
+            self.selector.clone(),
+            self.domain.clone(),
+            key,
+            headers,
+            DkimCanonicalization::default(),
+        ))
+    }
+}
+
+#[tokio::test]
+async fn test() -> Result<()> {
+    use std::sync::Arc;
+
+    use rsa::pkcs8::EncodePrivateKey;
+
+    use super::{Mailer, MemoryTransport};
+
+    let path = std::env::temp_dir().join("common_x_dkim_test.pem");
+    let key = RsaPrivateKey::new(&mut rsa::rand_core::OsRng, 1024)?;
+    std::fs::write(&path, key.to_pkcs8_pem(LineEnding::LF)?.as_bytes())?;
+    let mut config = DkimConfig {
+        selector: "mail".to_owned(),
+        domain: "example.com".to_owned(),
+        key: path.to_string_lossy().into_owned(),
+        ..Default::default()
+    };
+    let memory = MemoryTransport::default();
+    let mailer = Mailer::with_transport("noreply@example.com", Arc::new(memory.clone()))?
+        .with_dkim(&config)?;
+    mailer.send("Signed", "body", "a@example.com").await?;
+    let raw = String::from_utf8(memory.messages()[0].formatted())?;
+    assert!(raw.contains("DKIM-Signature: v=1; a=rsa-sha256; d=example.com; s=mail;"));
+    assert!(raw.contains("h=From:To:Subject:Date;"));
+
+    config.headers.push("Bad Name".to_owned());
+    assert!(config.signer().is_err());
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
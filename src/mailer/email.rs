@@ -0,0 +1,276 @@
+use std::path::Path;
+
+use color_eyre::{Result, eyre::eyre};
+use lettre::{
+    Address, Message,
+    address::Envelope,
+    message::{
+        Attachment as Part, Mailbox, MultiPart, MultiPartBuilder, SinglePart,
+        header::{ContentType, HeaderName, HeaderValue},
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use super::MailError;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Attachment {
+    pub filename: String,
+    /// e.g. `image/png`
+    pub content_type: String,
+    pub content: Vec<u8>,
+    /// set for inline parts, referenced from the html as `cid:{content_id}`
+    pub content_id: Option<String>,
+}
+
+/// Message builder, addresses and headers are checked by `build`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct Email {
+    /// overrides the mailer's from address for this message
+    pub from: Option<String>,
+    pub subject: String,
+    pub text: Option<String>,
+    pub html: Option<String>,
+    pub to: Vec<String>,
+    pub cc: Vec<String>,
+    pub bcc: Vec<String>,
+    pub reply_to: Vec<String>,
+    pub headers: Vec<(String, String)>,
+    pub attachments: Vec<Attachment>,
+}
+
+impl Email {
+    pub fn new(subject: &str) -> Self {
+        Self {
+            subject: subject.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    pub fn from(mut self, address: &str) -> Self {
+        self.from = Some(address.to_owned());
+        self
+    }
+
+    pub fn to(mut self, address: &str) -> Self {
+        self.to.push(address.to_owned());
+        self
+    }
+
+    pub fn cc(mut self, address: &str) -> Self {
+        self.cc.push(address.to_owned());
+        self
+    }
+
+    pub fn bcc(mut self, address: &str) -> Self {
+        self.bcc.push(address.to_owned());
+        self
+    }
+
+    pub fn reply_to(mut self, address: &str) -> Self {
+        self.reply_to.push(address.to_owned());
+        self
+    }
+
+    /// plain text body, the alternative when `html` is set too
+    pub fn text(mut self, body: &str) -> Self {
+        self.text = Some(body.to_owned());
+        self
+    }
+
+    pub fn html(mut self, body: &str) -> Self {
+        self.html = Some(body.to_owned());
+        self
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    pub fn attach(mut self, filename: &str, content_type: &str, content: Vec<u8>) -> Self {
+        self.attachments.push(Attachment {
+            filename: filename.to_owned(),
+            content_type: content_type.to_owned(),
+            content,
+            content_id: None,
+        });
+        self
+    }
+
+    /// Image or other part shown in the html as `<img src="cid:{content_id}">`.
+    pub fn inline(mut self, content_id: &str, content_type: &str, content: Vec<u8>) -> Self {
+        self.attachments.push(Attachment {
+            filename: content_id.to_owned(),
+            content_type: content_type.to_owned(),
+            content,
+            content_id: Some(content_id.to_owned()),
+        });
+        self
+    }
+
+    pub async fn attach_file(self, path: impl AsRef<Path>, content_type: &str) -> Result<Self> {
+        let path = path.as_ref();
+        let filename = path
+            .file_name()
+            .ok_or_else(|| eyre!("not a file: {path:?}"))?
+            .to_string_lossy()
+            .into_owned();
+        let content = tokio::fs::read(path).await?;
+        Ok(self.attach(&filename, content_type, content))
+    }
+
+    pub fn build(&self, from: &str) -> Result<Message, MailError> {
+        self.build_with(&mailbox(from)?, None)
+    }
+
+    /// `envelope_from` replaces the `MAIL FROM` derived from the From header.
+    pub(crate) fn build_with(
+        &self,
+        from: &Mailbox,
+        envelope_from: Option<&Address>,
+    ) -> Result<Message, MailError> {
+        let from = match &self.from {
+            Some(from) => mailbox(from)?,
+            None => from.clone(),
+        };
+        let mut builder = Message::builder().from(from).subject(&self.subject);
+        let mut recipients = Vec::new();
+        for address in &self.to {
+            let mailbox = mailbox(address)?;
+            recipients.push(mailbox.email.clone());
+            builder = builder.to(mailbox);
+        }
+        for address in &self.cc {
+            let mailbox = mailbox(address)?;
+            recipients.push(mailbox.email.clone());
+            builder = builder.cc(mailbox);
+        }
+        for address in &self.bcc {
+            let mailbox = mailbox(address)?;
+            recipients.push(mailbox.email.clone());
+            builder = builder.bcc(mailbox);
+        }
+        for address in &self.reply_to {
+            builder = builder.reply_to(mailbox(address)?);
+        }
+        if let Some(sender) = envelope_from {
+            builder = builder.envelope(Envelope::new(Some(sender.clone()), recipients)?);
+        }
+        for (name, value) in &self.headers {
+            let name = HeaderName::new_from_ascii(name.clone())
+                .map_err(|_| MailError::Message(format!("invalid header name: {name}")))?;
+            builder = builder.raw_header(HeaderValue::new(name, value.clone()));
+        }
+
+        let mut body = match (&self.text, &self.html) {
+            (Some(text), Some(html)) => Body::Multi(MultiPart::alternative_plain_html(
+                text.clone(),
+                html.clone(),
+            )),
+            (None, Some(html)) => Body::Single(SinglePart::html(html.clone())),
+            (text, None) => Body::Single(SinglePart::plain(text.clone().unwrap_or_default())),
+        };
+        let (inline, attached): (Vec<_>, Vec<_>) = self
+            .attachments
+            .iter()
+            .partition(|a| a.content_id.is_some());
+        for (parts, builder) in [
+            (inline, MultiPart::related()),
+            (attached, MultiPart::mixed()),
+        ] {
+            if parts.is_empty() {
+                continue;
+            }
+            let mut multipart = body.wrap(builder);
+            for part in parts {
+                multipart = multipart.singlepart(part.part()?);
+            }
+            body = Body::Multi(multipart);
+        }
+        Ok(match body {
+            Body::Single(part) => builder.singlepart(part)?,
+            Body::Multi(part) => builder.multipart(part)?,
+        })
+    }
+}
+
+impl Attachment {
+    fn part(&self) -> Result<SinglePart, MailError> {
+        let content_type = ContentType::parse(&self.content_type).map_err(|_| {
+            MailError::Message(format!("invalid content type: {}", self.content_type))
+        })?;
+        let part = match &self.content_id {
+            Some(content_id) => Part::new_inline(content_id.clone()),
+            None => Part::new(self.filename.clone()),
+        };
+        Ok(part.body(self.content.clone(), content_type))
+    }
+}
+
+enum Body {
+    Single(SinglePart),
+    Multi(MultiPart),
+}
+
+impl Body {
+    fn wrap(self, builder: MultiPartBuilder) -> MultiPart {
+        match self {
+            Self::Single(part) => builder.singlepart(part),
+            Self::Multi(part) => builder.multipart(part),
+        }
+    }
+}
+
+pub(crate) fn mailbox(address: &str) -> Result<Mailbox, MailError> {
+    address
+        .parse()
+        .map_err(|e| MailError::Address(format!("{address:?}: {e}")))
+}
+
+pub(crate) fn address(address: &str) -> Result<Address, MailError> {
+    address
+        .parse()
+        .map_err(|e| MailError::Address(format!("{address:?}: {e}")))
+}
+
+#[test]
+fn test() -> Result<()> {
+    let message = Email::new("Report")
+        .to("a@example.com")
+        .to("B <b@example.com>")
+        .cc("c@example.com")
+        .bcc("d@example.com")
+        .reply_to("support@example.com")
+        .header("X-Campaign", "weekly")
+        .text("see html")
+        .html(r#"<p>chart</p><img src="cid:chart">"#)
+        .inline("chart", "image/png", vec![0x89, b'P', b'N', b'G'])
+        .attach("report.csv", "text/csv", b"a,b\n1,2\n".to_vec())
+        .build("noreply@example.com")?;
+    let raw = String::from_utf8(message.formatted())?;
+    assert!(raw.contains("To: a@example.com, B <b@example.com>"));
+    assert!(raw.contains("Cc: c@example.com") && !raw.contains("d@example.com"));
+    assert!(raw.contains("Reply-To: support@example.com"));
+    assert!(raw.contains("X-Campaign: weekly"));
+    for part in [
+        "multipart/mixed",
+        "multipart/related",
+        "multipart/alternative",
+        "Content-ID: <chart>",
+        "filename=\"report.csv\"",
+    ] {
+        assert!(raw.contains(part), "{part} missing");
+    }
+    assert_eq!(message.envelope().to().len(), 4);
+
+    assert!(Email::new("x").to("not an address").build("a@b.c").is_err());
+    assert!(
+        Email::new("x")
+            .header("Bad Name", "v")
+            .build("a@b.c")
+            .is_err()
+    );
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use std::fmt;
+
+use lettre::transport::smtp;
+
+/// Why a mail was not sent; `is_retryable` tells whether trying later may help.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MailError {
+    /// unparsable from, to, cc, bcc or reply-to address
+    Address(String),
+    /// message could not be built, e.g. a bad header or no recipient
+    Message(String),
+    /// credentials rejected by the relay
+    Auth(String),
+    /// SMTP 4xx reply
+    Transient { code: u16, message: String },
+    /// SMTP 5xx reply
+    Permanent { code: u16, message: String },
+    /// connection, TLS or timeout failure before a reply
+    Transport(String),
+}
+
+impl MailError {
+    pub const fn is_retryable(&self) -> bool {
+        matches!(self, Self::Transient { .. } | Self::Transport(_))
+    }
+}
+
+impl fmt::Display for MailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Address(message) => write!(f, "invalid address: {message}"),
+            Self::Message(message) => write!(f, "invalid message: {message}"),
+            Self::Auth(message) => write!(f, "authentication failed: {message}"),
+            Self::Transient { code, message } => write!(f, "transient error {code}: {message}"),
+            Self::Permanent { code, message } => write!(f, "rejected {code}: {message}"),
+            Self::Transport(message) => write!(f, "transport error: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for MailError {}
+
+impl From<lettre::error::Error> for MailError {
+    fn from(e: lettre::error::Error) -> Self {
+        Self::Message(e.to_string())
+    }
+}
+
+impl From<smtp::Error> for MailError {
+    fn from(e: smtp::Error) -> Self {
+        let message = e.to_string();
+        match e.status().map(u16::from) {
+            Some(530 | 534 | 535 | 538) => Self::Auth(message),
+            Some(code) if e.is_transient() => Self::Transient { code, message },
+            Some(code) if e.is_permanent() => Self::Permanent { code, message },
+            _ => Self::Transport(message),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test() -> color_eyre::Result<()> {
+    use std::io::{BufRead, Write};
+
+    use lettre::{AsyncTransport, Message};
+
+    use super::{MailerConfig, TlsMode, transport::smtp_transport};
+    use crate::test_util::serve;
+
+    // stands in for a relay answering AUTH and MAIL FROM with the given replies
+    fn relay(auth: &'static str, mail: &'static str) -> std::io::Result<u16> {
+        serve(move |reader, stream| {
+            stream.write_all(b"220 localhost ESMTP\r\n").ok();
+            let mut line = String::new();
+            while reader.read_line(&mut line).is_ok_and(|n| n > 0) {
+                let reply = match line.get(..4).map(str::to_ascii_uppercase).as_deref() {
+                    Some("EHLO") => "250-localhost\r\n250 AUTH PLAIN",
+                    Some("AUTH") => auth,
+                    Some("MAIL") => mail,
+                    Some("QUIT") => "221 bye",
+                    _ => "250 ok",
+                };
+                stream.write_all(format!("{reply}\r\n").as_bytes()).ok();
+                line.clear();
+            }
+        })
+    }
+
+    let message = Message::builder()
+        .from("noreply@example.com".parse()?)
+        .to("name@example.com".parse()?)
+        .body("hello".to_owned())?;
+    let mut config = MailerConfig {
+        username: "noreply@example.com".to_owned(),
+        password: "wrong".to_owned(),
+        relay: "127.0.0.1".to_owned(),
+        tls: TlsMode::None,
+        timeout: 1000,
+        ..Default::default()
+    };
+    let mut send = async |auth, mail| -> color_eyre::Result<MailError> {
+        config.port = relay(auth, mail)?;
+        let sent = smtp_transport(&config)?.send(message.clone()).await;
+        Ok(MailError::from(sent.unwrap_err()))
+    };
+
+    let error = send("535 5.7.8 bad credentials", "250 ok").await?;
+    assert!(matches!(error, MailError::Auth(_)), "{error:?}");
+    let error = send("235 ok", "451 4.3.0 try again later").await?;
+    assert!(
+        matches!(error, MailError::Transient { code: 451, .. }) && error.is_retryable(),
+        "{error:?}"
+    );
+    let error = send("235 ok", "550 5.1.0 sender rejected").await?;
+    assert!(
+        matches!(error, MailError::Permanent { code: 550, .. }) && !error.is_retryable(),
+        "{error:?}"
+    );
+
+    config.port = 1;
+    let error = MailError::from(smtp_transport(&config)?.send(message).await.unwrap_err());
+    assert!(
+        matches!(error, MailError::Transport(_)) && error.is_retryable(),
+        "{error:?}"
+    );
+    Ok(())
+}
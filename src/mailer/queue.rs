@@ -0,0 +1,365 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use tokio::{fs, sync::Notify};
+use tracing::{info, warn};
+
+use super::{Email, Mailer};
+use crate::graceful_shutdown::CloseSignal;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct MailQueueConfig {
+    /// messages wait in `{dir}/pending`, give-ups go to `{dir}/dead`
+    pub dir: String,
+    pub max_attempts: u32,
+    /// milliseconds
+    pub backoff_min: u64,
+    /// milliseconds
+    pub backoff_max: u64,
+    /// sends per second to the queue's relay, 0 for unlimited
+    pub rate_limit: u32,
+    /// on close, try pending messages once more before persisting the rest,
+    /// stopping at the first relay error; drain attempts are not counted
+    pub drain_on_close: bool,
+}
+
+impl Default for MailQueueConfig {
+    fn default() -> Self {
+        Self {
+            dir: "mail_queue".to_owned(),
+            max_attempts: 5,
+            backoff_min: 1000,
+            backoff_max: 600_000,
+            rate_limit: 10,
+            drain_on_close: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct QueuedMail {
+    pub id: String,
+    pub email: Email,
+    pub attempts: u32,
+    /// unix milliseconds
+    pub next_attempt: u64,
+    pub last_error: Option<String>,
+}
+
+/// Outcome of sending a queued message once.
+enum Attempt {
+    /// sent or moved to `dead`
+    Settled,
+    /// still pending, due again at the given unix milliseconds
+    Due(u64),
+    /// relay unavailable while draining, left as it was
+    Unavailable,
+}
+
+/// On-disk outbound queue in front of a `Mailer`, see `run`.
+#[derive(Clone)]
+pub struct MailQueue {
+    config: MailQueueConfig,
+    mailer: Mailer,
+    wake: Arc<Notify>,
+}
+
+impl MailQueue {
+    pub fn new(config: MailQueueConfig, mailer: Mailer) -> Result<Self> {
+        let queue = Self {
+            config,
+            mailer,
+            wake: Default::default(),
+        };
+        std::fs::create_dir_all(queue.pending_dir())?;
+        std::fs::create_dir_all(queue.dead_dir())?;
+        Ok(queue)
+    }
+
+    fn pending_dir(&self) -> PathBuf {
+        Path::new(&self.config.dir).join("pending")
+    }
+
+    fn dead_dir(&self) -> PathBuf {
+        Path::new(&self.config.dir).join("dead")
+    }
+
+    /// Stores `email` for `run` to send, failing right away if it cannot be built.
+    pub async fn enqueue(&self, email: &Email) -> Result<String> {
+        self.mailer.message(email)?;
+        static SEQ: AtomicU64 = AtomicU64::new(0);
+        let mail = QueuedMail {
+            id: format!(
+                "{:013}-{:06}",
+                now_millis(),
+                SEQ.fetch_add(1, Ordering::Relaxed) % 1_000_000
+            ),
+            email: email.clone(),
+            attempts: 0,
+            next_attempt: 0,
+            last_error: None,
+        };
+        write(&self.pending_dir(), &mail).await?;
+        self.wake.notify_one();
+        Ok(mail.id)
+    }
+
+    /// Unreadable entries are moved to `{dir}/dead` with a `.corrupt` extension.
+    pub async fn pending(&self) -> Result<Vec<QueuedMail>> {
+        read_dir(&self.pending_dir(), Some(&self.dead_dir())).await
+    }
+
+    pub async fn dead_letters(&self) -> Result<Vec<QueuedMail>> {
+        read_dir(&self.dead_dir(), None).await
+    }
+
+    /// Sends due messages until `signal` is closed, then drains or just leaves
+    /// the rest on disk for the next start, e.g. as a `Supervisor` child.
+    pub async fn run(&self, signal: CloseSignal) -> Result<()> {
+        let mut last_send = None;
+        loop {
+            let next = self.send_pending(Some(&signal), &mut last_send).await?;
+            let wait = next.map_or(60_000, |t| t.saturating_sub(now_millis()));
+            tokio::select! {
+                _ = signal.closed_async() => break,
+                _ = self.wake.notified() => {}
+                _ = tokio::time::sleep(Duration::from_millis(wait)) => {}
+            }
+        }
+        if self.config.drain_on_close {
+            self.send_pending(None, &mut last_send).await?;
+        }
+        info!(
+            "mail queue stopped, {} pending",
+            self.pending().await?.len()
+        );
+        Ok(())
+    }
+
+    /// Returns when the earliest message left is due. Only fails when the
+    /// pending directory cannot be read, a failing message is logged and skipped.
+    ///
+    /// Without `signal` every message is tried regardless of when it is due
+    /// until the relay fails, otherwise the rest is left on disk once `signal`
+    /// is closed.
+    async fn send_pending(
+        &self,
+        signal: Option<&CloseSignal>,
+        last_send: &mut Option<Instant>,
+    ) -> Result<Option<u64>> {
+        let mut next = None;
+        for mail in self.pending().await? {
+            if signal.is_some_and(CloseSignal::is_closed) {
+                break;
+            }
+            let id = mail.id.clone();
+            let due = if signal.is_some() && mail.next_attempt > now_millis() {
+                mail.next_attempt
+            } else {
+                match self.attempt(mail, last_send, signal.is_none()).await {
+                    Ok(Attempt::Due(due)) => due,
+                    Ok(Attempt::Settled) => continue,
+                    Ok(Attempt::Unavailable) => break,
+                    Err(e) => {
+                        warn!("mail {id} left in queue: {e}");
+                        continue;
+                    }
+                }
+            };
+            next = Some(next.map_or(due, |n: u64| n.min(due)));
+        }
+        Ok(next)
+    }
+
+    /// Sends `mail` once. While `draining`, a retryable failure leaves it untouched.
+    async fn attempt(
+        &self,
+        mut mail: QueuedMail,
+        last_send: &mut Option<Instant>,
+        draining: bool,
+    ) -> Result<Attempt> {
+        if self.config.rate_limit > 0 {
+            let interval = Duration::from_secs(1) / self.config.rate_limit;
+            if let Some(last) = last_send {
+                tokio::time::sleep(interval.saturating_sub(last.elapsed())).await;
+            }
+            *last_send = Some(Instant::now());
+        }
+
+        let path = self.pending_dir().join(format!("{}.json", mail.id));
+        let Err(e) = self.mailer.send_email(&mail.email).await else {
+            fs::remove_file(path).await?;
+            return Ok(Attempt::Settled);
+        };
+        if draining && e.is_retryable() {
+            warn!("mail relay unavailable, leaving the queue for the next start: {e}");
+            return Ok(Attempt::Unavailable);
+        }
+        mail.attempts += 1;
+        mail.last_error = Some(e.to_string());
+        if !e.is_retryable() || mail.attempts >= self.config.max_attempts {
+            warn!(
+                "mail {} dead after {} attempts: {e}",
+                mail.id, mail.attempts
+            );
+            write(&self.dead_dir(), &mail).await?;
+            fs::remove_file(path).await?;
+            Ok(Attempt::Settled)
+        } else {
+            mail.next_attempt = now_millis() + self.backoff(mail.attempts);
+            write(&self.pending_dir(), &mail).await?;
+            Ok(Attempt::Due(mail.next_attempt))
+        }
+    }
+
+    fn backoff(&self, attempts: u32) -> u64 {
+        let shift = attempts.saturating_sub(1).min(31);
+        self.config
+            .backoff_min
+            .saturating_mul(1 << shift)
+            .min(self.config.backoff_max)
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Written to a temporary file first, so a crash never leaves half a message.
+async fn write(dir: &Path, mail: &QueuedMail) -> Result<()> {
+    let path = dir.join(format!("{}.json", mail.id));
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_vec(mail)?).await?;
+    fs::rename(tmp, path).await?;
+    Ok(())
+}
+
+/// Entries that cannot be read are logged and skipped, or moved to `dead`.
+async fn read_dir(dir: &Path, dead: Option<&Path>) -> Result<Vec<QueuedMail>> {
+    let mut paths = Vec::new();
+    let mut entries = fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().is_some_and(|e| e == "json") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    let mut mails = Vec::with_capacity(paths.len());
+    for path in paths {
+        let mail = match fs::read(&path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        match mail {
+            Ok(mail) => mails.push(mail),
+            Err(e) => {
+                warn!("unreadable queued mail {}: {e}", path.display());
+                if let (Some(dead), Some(name)) = (dead, path.file_name()) {
+                    let corrupt = dead.join(name).with_extension("corrupt");
+                    if let Err(e) = fs::rename(&path, corrupt).await {
+                        warn!("failed to move {} to dead: {e}", path.display());
+                    }
+                }
+            }
+        }
+    }
+    Ok(mails)
+}
+
+#[tokio::test]
+async fn test() -> Result<()> {
+    use super::MailerConfig;
+    use crate::graceful_shutdown::CloseToken;
+
+    let dir = std::env::temp_dir().join("common_x_mail_queue_test");
+    std::fs::remove_dir_all(&dir).ok();
+    let mailer = Mailer::new(&MailerConfig {
+        username: "queue@example.com".to_owned(),
+        relay: "127.0.0.1".to_owned(),
+        port: 1,
+        timeout: 1000,
+        ..Default::default()
+    })?;
+    let config = MailQueueConfig {
+        dir: dir.to_string_lossy().into_owned(),
+        max_attempts: 2,
+        backoff_min: 10,
+        rate_limit: 0,
+        ..Default::default()
+    };
+    let queue = MailQueue::new(config.clone(), mailer.clone())?;
+    assert!(queue.enqueue(&Email::new("x").to("bad")).await.is_err());
+    let id = queue
+        .enqueue(&Email::new("hi").to("a@example.com").text("hello"))
+        .await?;
+    let restarted = MailQueue::new(config.clone(), mailer.clone())?;
+    assert_eq!(restarted.pending().await?[0].id, id);
+    std::fs::write(dir.join("pending/0-bad.json"), "{not json")?;
+
+    let token = CloseToken::default();
+    let signal = token.signal();
+    let run = tokio::spawn(async move { restarted.run(signal).await });
+    for _ in 0..100 {
+        if !queue.dead_letters().await?.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    token.close();
+    run.await??;
+
+    let dead = queue.dead_letters().await?;
+    assert_eq!(dead.len(), 1);
+    assert_eq!(dead[0].attempts, 2);
+    assert!(dead[0].last_error.is_some());
+    assert!(queue.pending().await?.is_empty());
+    assert!(dir.join("dead/0-bad.corrupt").exists());
+
+    // once closed, a backlog is left on disk instead of sent at the rate limit
+    let queue = MailQueue::new(
+        MailQueueConfig {
+            rate_limit: 1,
+            drain_on_close: false,
+            ..config
+        },
+        mailer,
+    )?;
+    for _ in 0..3 {
+        queue
+            .enqueue(&Email::new("hi").to("a@example.com").text("hello"))
+            .await?;
+    }
+    token.close();
+    tokio::time::timeout(Duration::from_millis(500), queue.run(token.signal())).await??;
+    let pending = queue.pending().await?;
+    assert!(pending.len() == 3 && pending.iter().all(|m| m.attempts == 0));
+
+    // draining stops at the first failure, without counting it as an attempt
+    let queue = MailQueue::new(
+        MailQueueConfig {
+            drain_on_close: true,
+            ..queue.config.clone()
+        },
+        queue.mailer.clone(),
+    )?;
+    let started = Instant::now();
+    queue.run(token.signal()).await?;
+    assert!(started.elapsed() < Duration::from_millis(1500));
+    let pending = queue.pending().await?;
+    assert!(pending.len() == 3 && pending.iter().all(|m| m.attempts == 0));
+    assert!(queue.dead_letters().await?.len() == 1);
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
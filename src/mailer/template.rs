@@ -0,0 +1,192 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use color_eyre::{Result, eyre::eyre};
+use minijinja::{
+    Environment,
+    value::{Serde, Value},
+};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use parking_lot::RwLock;
+use serde::Serialize;
+use tracing::{error, info};
+
+use super::Email;
+
+#[derive(Clone, Debug, Default)]
+struct Template {
+    subject: String,
+    html: Option<String>,
+    text: Option<String>,
+}
+
+/// Named email templates in `{dir}/{locale}/{name}.subject`, `.html` and `.txt`,
+/// rendered with minijinja. Html is escaped, a missing locale falls back from
+/// `zh-CN` to `zh` and then to the default locale.
+#[derive(Clone, Debug)]
+pub struct TemplateRegistry {
+    dir: PathBuf,
+    default_locale: String,
+    templates: Arc<RwLock<HashMap<(String, String), Template>>>,
+}
+
+impl TemplateRegistry {
+    pub fn load(dir: impl AsRef<Path>, default_locale: &str) -> Result<Self> {
+        let registry = Self {
+            dir: dir.as_ref().to_path_buf(),
+            default_locale: default_locale.to_owned(),
+            templates: Default::default(),
+        };
+        registry.reload()?;
+        Ok(registry)
+    }
+
+    pub fn reload(&self) -> Result<()> {
+        let mut templates: HashMap<(String, String), Template> = HashMap::new();
+        for locale in fs::read_dir(&self.dir)? {
+            let locale = locale?;
+            if !locale.file_type()?.is_dir() {
+                continue;
+            }
+            let locale_name = locale.file_name().to_string_lossy().into_owned();
+            for file in fs::read_dir(locale.path())? {
+                let path = file?.path();
+                let (Some(name), Some(extension)) = (
+                    path.file_stem().and_then(|s| s.to_str()),
+                    path.extension().and_then(|e| e.to_str()),
+                ) else {
+                    continue;
+                };
+                let template = templates
+                    .entry((name.to_owned(), locale_name.clone()))
+                    .or_default();
+                match extension {
+                    "subject" => template.subject = fs::read_to_string(&path)?.trim().to_owned(),
+                    "html" => template.html = Some(fs::read_to_string(&path)?),
+                    "txt" => template.text = Some(fs::read_to_string(&path)?),
+                    _ => {}
+                }
+            }
+        }
+        *self.templates.write() = templates;
+        Ok(())
+    }
+
+    /// Reloads on any change under the directory until the watcher is dropped.
+    pub fn watch(&self) -> Result<RecommendedWatcher> {
+        let registry = self.clone();
+        let mut watcher = RecommendedWatcher::new(
+            move |result: Result<Event, notify::Error>| match result {
+                Ok(event) if event.kind.is_access() => {}
+                Ok(_) => match registry.reload() {
+                    Ok(_) => info!("reloaded mail templates"),
+                    Err(error) => error!("Error reloading mail templates: {:?}", error),
+                },
+                Err(error) => error!("Error watching mail templates: {:?}", error),
+            },
+            notify::Config::default(),
+        )?;
+        watcher.watch(&self.dir, RecursiveMode::Recursive)?;
+        Ok(watcher)
+    }
+
+    /// Subject and bodies of `name`, ready for recipients to be added.
+    pub fn render(&self, name: &str, locale: &str, context: impl Serialize) -> Result<Email> {
+        let templates = self.templates.read();
+        let template = self
+            .locales(locale)
+            .iter()
+            .find_map(|locale| templates.get(&(name.to_owned(), locale.clone())))
+            .ok_or_else(|| eyre!("no mail template {name} for locale {locale}"))?;
+
+        let env = Environment::new();
+        let context = Value::from(Serde(context));
+        let render = |part: &str, source: &str| -> Result<String> {
+            let name = format!("{name}.{part}");
+            Ok(env
+                .template_from_named_str(&name, source)?
+                .render(&context)?)
+        };
+        let mut email = Email::new(&render("subject", &template.subject)?);
+        if let Some(html) = &template.html {
+            email.html = Some(render("html", html)?);
+        }
+        if let Some(text) = &template.text {
+            email.text = Some(render("txt", text)?);
+        }
+        Ok(email)
+    }
+
+    fn locales(&self, locale: &str) -> Vec<String> {
+        let mut locales = vec![locale.to_owned()];
+        if let Some((language, _)) = locale.split_once(['-', '_']) {
+            locales.push(language.to_owned());
+        }
+        locales.push(self.default_locale.clone());
+        locales
+    }
+}
+
+#[test]
+fn test() -> Result<()> {
+    let dir = std::env::temp_dir().join("common_x_mail_template_test");
+    fs::remove_dir_all(&dir).ok();
+    fs::create_dir_all(dir.join("en"))?;
+    fs::create_dir_all(dir.join("zh"))?;
+    fs::write(dir.join("en/verify.subject"), "Verify {{ user }}\n")?;
+    fs::write(
+        dir.join("en/verify.html"),
+        "<p>Hi {{ user }}, code {{ code }}</p>",
+    )?;
+    fs::write(dir.join("en/verify.txt"), "Hi {{ user }}, code {{ code }}")?;
+    fs::write(dir.join("zh/verify.subject"), "验证 {{ user }}")?;
+    fs::write(dir.join("zh/verify.txt"), "验证码 {{ code }}")?;
+
+    let registry = TemplateRegistry::load(&dir, "en")?;
+    let context = serde_json::json!({"user": "<jl>", "code": 42});
+    let email = registry.render("verify", "en", &context)?;
+    assert_eq!(email.subject, "Verify <jl>");
+    assert_eq!(email.html.as_deref(), Some("<p>Hi &lt;jl&gt;, code 42</p>"));
+    assert_eq!(email.text.as_deref(), Some("Hi <jl>, code 42"));
+
+    let email = registry.render("verify", "zh-CN", &context)?;
+    assert_eq!(email.subject, "验证 <jl>");
+    assert_eq!(email.html, None);
+    assert_eq!(
+        registry.render("verify", "fr", &context)?.subject,
+        "Verify <jl>"
+    );
+    assert!(registry.render("reset", "en", &context).is_err());
+
+    fs::write(dir.join("en/verify.subject"), "Confirm {{ user }}")?;
+    registry.reload()?;
+    assert_eq!(
+        registry.render("verify", "en", &context)?.subject,
+        "Confirm <jl>"
+    );
+    fs::write(dir.join("en/verify.subject"), "Confirm {{ user")?;
+    registry.reload()?;
+    let error = registry.render("verify", "en", &context).unwrap_err();
+    assert!(error.to_string().contains("verify.subject"), "{error}");
+    fs::write(dir.join("en/verify.subject"), "Confirm {{ user }}")?;
+    registry.reload()?;
+
+    let _watcher = registry.watch()?;
+    fs::write(dir.join("en/verify.subject"), "Welcome {{ user }}")?;
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    while registry.render("verify", "en", &context)?.subject != "Welcome <jl>"
+        && std::time::Instant::now() < deadline
+    {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert_eq!(
+        registry.render("verify", "en", &context)?.subject,
+        "Welcome <jl>"
+    );
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
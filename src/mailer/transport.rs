@@ -0,0 +1,174 @@
+use std::{path::Path, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use color_eyre::Result;
+use lettre::{
+    AsyncFileTransport, AsyncSendmailTransport, AsyncSmtpTransport, AsyncTransport, Message,
+    Tokio1Executor,
+    transport::smtp::{
+        PoolConfig,
+        authentication::Credentials,
+        client::{Certificate, Tls, TlsParameters},
+    },
+};
+use parking_lot::Mutex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+use super::{MailError, MailerConfig};
+use crate::tls::read_certs;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MailTransport {
+    #[default]
+    Smtp,
+    /// writes every message to `{dir}/{uuid}.eml` instead of sending it
+    File(String),
+    /// pipes messages to a sendmail compatible command, `None` for `sendmail` in PATH
+    Sendmail(Option<String>),
+}
+
+/// Also read from the former `tls` bool, `true` being `StartTlsRequired` and
+/// `false` `Implicit`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(remote = "Self")]
+pub enum TlsMode {
+    /// plain text, only for a trusted local MTA
+    None,
+    StartTlsRequired,
+    /// upgrades when the relay offers STARTTLS, plain text otherwise
+    StartTlsOpportunistic,
+    /// TLS from the first byte, usually port 465
+    #[default]
+    Implicit,
+}
+
+impl Serialize for TlsMode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Self::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for TlsMode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // untagged rather than a visitor, as ron only names bare variants to serde's buffer
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Compat {
+            Bool(bool),
+            #[serde(with = "TlsMode")]
+            Mode(TlsMode),
+            Unknown(String),
+        }
+        match Compat::deserialize(deserializer)? {
+            Compat::Bool(true) => Ok(Self::StartTlsRequired),
+            Compat::Bool(false) => Ok(Self::Implicit),
+            Compat::Mode(mode) => Ok(mode),
+            Compat::Unknown(mode) => Err(de::Error::unknown_variant(
+                &mode,
+                &[
+                    "None",
+                    "StartTlsRequired",
+                    "StartTlsOpportunistic",
+                    "Implicit",
+                ],
+            )),
+        }
+    }
+}
+
+/// Delivers built messages for a `Mailer`.
+#[async_trait]
+pub trait Transport: Send + Sync {
+    async fn send(&self, message: Message) -> Result<(), MailError>;
+}
+
+#[async_trait]
+impl Transport for AsyncSmtpTransport<Tokio1Executor> {
+    async fn send(&self, message: Message) -> Result<(), MailError> {
+        AsyncTransport::send(self, message).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Transport for AsyncFileTransport<Tokio1Executor> {
+    async fn send(&self, message: Message) -> Result<(), MailError> {
+        AsyncTransport::send(self, message)
+            .await
+            .map_err(|e| MailError::Transport(e.to_string()))?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Transport for AsyncSendmailTransport<Tokio1Executor> {
+    async fn send(&self, message: Message) -> Result<(), MailError> {
+        AsyncTransport::send(self, message)
+            .await
+            .map_err(|e| MailError::Transport(e.to_string()))
+    }
+}
+
+/// Records messages instead of sending them, clones share the record.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryTransport {
+    messages: Arc<Mutex<Vec<Message>>>,
+}
+
+impl MemoryTransport {
+    pub fn messages(&self) -> Vec<Message> {
+        self.messages.lock().clone()
+    }
+
+    pub fn clear(&self) {
+        self.messages.lock().clear();
+    }
+}
+
+#[async_trait]
+impl Transport for MemoryTransport {
+    async fn send(&self, message: Message) -> Result<(), MailError> {
+        self.messages.lock().push(message);
+        Ok(())
+    }
+}
+
+pub(crate) fn smtp_transport(config: &MailerConfig) -> Result<AsyncSmtpTransport<Tokio1Executor>> {
+    let tls = if config.tls == TlsMode::None {
+        Tls::None
+    } else {
+        let mut builder = TlsParameters::builder(config.relay.clone());
+        if let Some(ca) = &config.ca {
+            for cert in read_certs(ca.clone())? {
+                builder = builder.add_root_certificate(Certificate::from_der(cert.to_vec())?);
+            }
+        }
+        let parameters = builder.build()?;
+        match config.tls {
+            TlsMode::StartTlsRequired => Tls::Required(parameters),
+            TlsMode::StartTlsOpportunistic => Tls::Opportunistic(parameters),
+            _ => Tls::Wrapper(parameters),
+        }
+    };
+    Ok(
+        AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.relay)
+            .port(config.port)
+            .tls(tls)
+            .credentials(Credentials::new(
+                config.username.clone(),
+                config.password.clone(),
+            ))
+            .timeout(Some(Duration::from_millis(config.timeout)))
+            .pool_config(
+                PoolConfig::new()
+                    .max_size(config.pool_size)
+                    .idle_timeout(Duration::from_millis(config.idle_timeout)),
+            )
+            .build(),
+    )
+}
+
+pub(crate) fn file_transport(dir: &str) -> std::io::Result<AsyncFileTransport<Tokio1Executor>> {
+    std::fs::create_dir_all(dir)?;
+    Ok(AsyncFileTransport::new(Path::new(dir)))
+}
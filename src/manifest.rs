@@ -0,0 +1,204 @@
+use std::{
+    collections::BTreeMap,
+    fs::Metadata,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::{Result, eyre::bail};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::{
+    file::{read_file_to_string, write_file},
+    hasher::{Digest, Hasher, hash_async_reader},
+};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileEntry {
+    pub mode: u32,
+    pub size: u64,
+    pub digest: Digest,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    Added(String),
+    Removed(String),
+    Modified(String),
+}
+
+/// Per-file BLAKE3 digests of a directory tree, keyed by `/`-separated relative path.
+///
+/// Symlinks are recorded by their target rather than followed.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Manifest {
+    pub files: BTreeMap<String, FileEntry>,
+}
+
+impl Manifest {
+    pub async fn scan(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref();
+        let mut files = BTreeMap::new();
+        let mut pending = vec![PathBuf::new()];
+        while let Some(relative) = pending.pop() {
+            let mut entries = fs::read_dir(dir.join(&relative)).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let relative = relative.join(entry.file_name());
+                let metadata = fs::symlink_metadata(entry.path()).await?;
+                let digest = if metadata.is_dir() {
+                    pending.push(relative);
+                    continue;
+                } else if metadata.is_symlink() {
+                    let target = fs::read_link(entry.path()).await?;
+                    Hasher::new()
+                        .update(target.to_string_lossy().as_bytes())
+                        .finalize()
+                } else {
+                    hash_async_reader(fs::File::open(entry.path()).await?).await?
+                };
+                files.insert(
+                    path_key(&relative),
+                    FileEntry {
+                        mode: mode(&metadata),
+                        size: metadata.len(),
+                        digest,
+                    },
+                );
+            }
+        }
+        Ok(Self { files })
+    }
+
+    /// Single digest over the sorted paths, modes and contents.
+    pub fn root(&self) -> Digest {
+        let mut hasher = Hasher::new();
+        for (path, entry) in &self.files {
+            hasher
+                .update(&(path.len() as u64).to_le_bytes())
+                .update(path.as_bytes())
+                .update(&entry.mode.to_le_bytes())
+                .update(entry.digest.as_bytes());
+        }
+        hasher.finalize()
+    }
+
+    /// Changes needed to turn `self` into `other`.
+    pub fn diff(&self, other: &Manifest) -> Vec<Change> {
+        let mut changes = Vec::new();
+        for (path, entry) in &self.files {
+            match other.files.get(path) {
+                None => changes.push(Change::Removed(path.clone())),
+                Some(e) if e != entry => changes.push(Change::Modified(path.clone())),
+                _ => {}
+            }
+        }
+        for path in other.files.keys() {
+            if !self.files.contains_key(path) {
+                changes.push(Change::Added(path.clone()));
+            }
+        }
+        changes
+    }
+
+    /// Drift of `dir` on disk from this manifest; empty when they match.
+    pub async fn verify(&self, dir: impl AsRef<Path>) -> Result<Vec<Change>> {
+        Ok(self.diff(&Self::scan(dir).await?))
+    }
+
+    /// Format picked by extension, `.ron` or `.json`.
+    pub async fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = read_file_to_string(path).await?;
+        Ok(match extension(path)? {
+            Format::Ron => ron::from_str(&content)?,
+            Format::Json => serde_json::from_str(&content)?,
+        })
+    }
+
+    pub async fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let content = match extension(path)? {
+            Format::Ron => ron::ser::to_string_pretty(self, Default::default())?,
+            Format::Json => serde_json::to_string_pretty(self)?,
+        };
+        write_file(path, content.as_bytes()).await
+    }
+}
+
+pub async fn hash_dir(dir: impl AsRef<Path>) -> Result<Digest> {
+    Ok(Manifest::scan(dir).await?.root())
+}
+
+enum Format {
+    Ron,
+    Json,
+}
+
+fn extension(path: &Path) -> Result<Format> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("ron") => Ok(Format::Ron),
+        Some("json") => Ok(Format::Json),
+        _ => bail!("unsupported manifest format: {:?}", path.to_str()),
+    }
+}
+
+fn path_key(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(unix)]
+fn mode(metadata: &Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode()
+}
+
+#[cfg(not(unix))]
+fn mode(metadata: &Metadata) -> u32 {
+    if metadata.permissions().readonly() {
+        0o444
+    } else {
+        0o644
+    }
+}
+
+#[tokio::test]
+async fn test() -> Result<()> {
+    use crate::file::create_file;
+
+    let dir = std::env::temp_dir().join("common_x_manifest_test");
+    fs::remove_dir_all(&dir).await.ok();
+    create_file(dir.join("a.txt"), b"a").await?;
+    create_file(dir.join("sub/b.txt"), b"b").await?;
+
+    let manifest = Manifest::scan(&dir).await?;
+    assert_eq!(
+        manifest.files.keys().collect::<Vec<_>>(),
+        ["a.txt", "sub/b.txt"]
+    );
+    assert_eq!(hash_dir(&dir).await?, manifest.root());
+
+    let saved = std::env::temp_dir().join("common_x_manifest_test.ron");
+    manifest.save(&saved).await?;
+    assert_eq!(Manifest::load(&saved).await?, manifest);
+    fs::remove_file(&saved).await?;
+    let saved = saved.with_extension("json");
+    manifest.save(&saved).await?;
+    assert_eq!(Manifest::load(&saved).await?, manifest);
+    fs::remove_file(&saved).await?;
+
+    write_file(dir.join("sub/b.txt"), b"tampered").await?;
+    create_file(dir.join("c.txt"), b"c").await?;
+    assert_eq!(
+        manifest.verify(&dir).await?,
+        [
+            Change::Modified("sub/b.txt".to_owned()),
+            Change::Added("c.txt".to_owned())
+        ]
+    );
+    assert_ne!(hash_dir(&dir).await?, manifest.root());
+    fs::remove_dir_all(&dir).await?;
+    Ok(())
+}
@@ -0,0 +1,298 @@
+use std::io::Read;
+
+use color_eyre::{
+    Result,
+    eyre::{bail, eyre},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::hasher::{Digest, Hasher};
+
+pub const DEFAULT_CHUNK_SIZE: usize = 1 << 20;
+
+const LEAF: u8 = 0;
+const NODE: u8 = 1;
+const PROOF_MAGIC: &[u8; 4] = b"CXMP";
+const PROOF_VERSION: u8 = 1;
+
+pub fn leaf_hash(chunk: &[u8]) -> Digest {
+    Hasher::new().update(&[LEAF]).update(chunk).finalize()
+}
+
+fn node_hash(left: &Digest, right: &Digest) -> Digest {
+    Hasher::new()
+        .update(&[NODE])
+        .update(left.as_bytes())
+        .update(right.as_bytes())
+        .finalize()
+}
+
+/// Builds every level above `leaves`; an odd node out is promoted unchanged.
+fn build_levels(leaves: Vec<Digest>) -> Vec<Vec<Digest>> {
+    let mut levels = vec![leaves];
+    while levels.last().unwrap().len() > 1 {
+        let next = levels
+            .last()
+            .unwrap()
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => node_hash(left, right),
+                [single] => *single,
+                _ => unreachable!(),
+            })
+            .collect();
+        levels.push(next);
+    }
+    levels
+}
+
+/// Merkle tree over fixed-size chunks. Empty input is a single empty chunk,
+/// a `chunk_size` of 0 is taken as 1.
+#[derive(Clone, Debug)]
+pub struct MerkleTree {
+    chunk_size: usize,
+    levels: Vec<Vec<Digest>>,
+}
+
+impl MerkleTree {
+    pub fn new(data: &[u8], chunk_size: usize) -> Self {
+        let chunk_size = chunk_size.max(1);
+        let mut leaves: Vec<_> = data.chunks(chunk_size).map(leaf_hash).collect();
+        if leaves.is_empty() {
+            leaves.push(leaf_hash(&[]));
+        }
+        Self {
+            chunk_size,
+            levels: build_levels(leaves),
+        }
+    }
+
+    pub fn from_reader(mut reader: impl Read, chunk_size: usize) -> Result<Self> {
+        let chunk_size = chunk_size.max(1);
+        let mut leaves = Vec::new();
+        let mut buf = vec![0; chunk_size];
+        loop {
+            let mut filled = 0;
+            while filled < buf.len() {
+                let n = reader.read(&mut buf[filled..])?;
+                if n == 0 {
+                    break;
+                }
+                filled += n;
+            }
+            if filled > 0 || leaves.is_empty() {
+                leaves.push(leaf_hash(&buf[..filled]));
+            }
+            if filled < buf.len() {
+                break;
+            }
+        }
+        Ok(Self {
+            chunk_size,
+            levels: build_levels(leaves),
+        })
+    }
+
+    pub fn root(&self) -> Digest {
+        self.levels.last().unwrap()[0]
+    }
+
+    pub const fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    pub fn leaves(&self) -> &[Digest] {
+        &self.levels[0]
+    }
+
+    pub fn proof(&self, index: usize) -> Option<Proof> {
+        let leaf_count = self.leaves().len();
+        if index >= leaf_count {
+            return None;
+        }
+        let mut siblings = Vec::new();
+        let mut idx = index;
+        for level in &self.levels[..self.levels.len() - 1] {
+            if let Some(sibling) = level.get(idx ^ 1) {
+                siblings.push(*sibling);
+            }
+            idx /= 2;
+        }
+        Some(Proof {
+            index: index as u64,
+            leaf_count: leaf_count as u64,
+            siblings,
+        })
+    }
+}
+
+/// Inclusion proof of one chunk.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Proof {
+    pub index: u64,
+    pub leaf_count: u64,
+    pub siblings: Vec<Digest>,
+}
+
+impl Proof {
+    /// Root implied by `chunk` at the claimed position. The position itself is not
+    /// bound by the digests, an odd node out is promoted unchanged, so use `verify`
+    /// with the expected position.
+    pub fn root(&self, chunk: &[u8]) -> Result<Digest> {
+        if self.index >= self.leaf_count {
+            bail!(
+                "proof index {} out of {} leaves",
+                self.index,
+                self.leaf_count
+            );
+        }
+        let mut siblings = self.siblings.iter();
+        let mut hash = leaf_hash(chunk);
+        let (mut idx, mut len) = (self.index, self.leaf_count);
+        while len > 1 {
+            if idx ^ 1 < len {
+                let sibling = siblings.next().ok_or_else(|| eyre!("proof too short"))?;
+                hash = if idx % 2 == 0 {
+                    node_hash(&hash, sibling)
+                } else {
+                    node_hash(sibling, &hash)
+                };
+            }
+            idx /= 2;
+            len = len.div_ceil(2);
+        }
+        if siblings.next().is_some() {
+            bail!("proof too long");
+        }
+        Ok(hash)
+    }
+
+    /// `chunk` is chunk `index` of `leaf_count` under `root`.
+    pub fn verify(&self, root: &Digest, index: u64, leaf_count: u64, chunk: &[u8]) -> bool {
+        self.index == index
+            && self.leaf_count == leaf_count
+            && self.root(chunk).is_ok_and(|r| r == *root)
+    }
+
+    /// `CXMP`, version, index and leaf count as u64 LE, sibling count as u8, siblings.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(22 + self.siblings.len() * 32);
+        bytes.extend_from_slice(PROOF_MAGIC);
+        bytes.push(PROOF_VERSION);
+        bytes.extend_from_slice(&self.index.to_le_bytes());
+        bytes.extend_from_slice(&self.leaf_count.to_le_bytes());
+        bytes.push(self.siblings.len() as u8);
+        for sibling in &self.siblings {
+            bytes.extend_from_slice(sibling.as_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < 22 || &bytes[..4] != PROOF_MAGIC {
+            bail!("not a merkle proof");
+        }
+        if bytes[4] != PROOF_VERSION {
+            bail!("unsupported merkle proof version: {}", bytes[4]);
+        }
+        let index = u64::from_le_bytes(bytes[5..13].try_into()?);
+        let leaf_count = u64::from_le_bytes(bytes[13..21].try_into()?);
+        let count = bytes[21] as usize;
+        let rest = &bytes[22..];
+        if rest.len() != count * 32 {
+            bail!("merkle proof length mismatch");
+        }
+        let siblings = rest
+            .chunks(32)
+            .map(Digest::from_slice)
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            index,
+            leaf_count,
+            siblings,
+        })
+    }
+}
+
+/// Verifies chunks as they arrive against a trusted root, given the leaf digests
+/// (e.g. shipped in a manifest alongside the root).
+#[derive(Clone, Debug)]
+pub struct ChunkVerifier {
+    root: Digest,
+    leaves: Vec<Digest>,
+}
+
+impl ChunkVerifier {
+    pub fn new(root: Digest, leaves: Vec<Digest>) -> Result<Self> {
+        if leaves.is_empty() {
+            bail!("no leaves");
+        }
+        let levels = build_levels(leaves);
+        if levels.last().unwrap()[0] != root {
+            bail!("leaves do not match root {root}");
+        }
+        Ok(Self {
+            root,
+            leaves: levels.into_iter().next().unwrap(),
+        })
+    }
+
+    pub const fn root(&self) -> &Digest {
+        &self.root
+    }
+
+    pub fn leaf_count(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn verify_chunk(&self, index: usize, chunk: &[u8]) -> Result<()> {
+        let leaf = self
+            .leaves
+            .get(index)
+            .ok_or_else(|| eyre!("chunk index {index} out of {} chunks", self.leaves.len()))?;
+        if leaf_hash(chunk) != *leaf {
+            bail!("chunk {index} does not match root {}", self.root);
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test() -> Result<()> {
+    let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+    let tree = MerkleTree::new(&data, 1024);
+    assert_eq!(tree.leaves().len(), 10);
+    assert_eq!(
+        MerkleTree::from_reader(data.as_slice(), 1024)?.root(),
+        tree.root()
+    );
+
+    let verifier = ChunkVerifier::new(tree.root(), tree.leaves().to_vec())?;
+    for (i, chunk) in data.chunks(1024).enumerate() {
+        let proof = tree.proof(i).unwrap();
+        assert!(proof.verify(&tree.root(), i as u64, 10, chunk));
+        assert!(!proof.verify(&tree.root(), i as u64, 10, &chunk[1..]));
+        assert_eq!(Proof::from_bytes(&proof.to_bytes())?, proof);
+        verifier.verify_chunk(i, chunk)?;
+    }
+    assert!(verifier.verify_chunk(0, &data[1024..2048]).is_err());
+    assert!(tree.proof(10).is_none());
+    assert_eq!(MerkleTree::new(&[], 1024).leaves().len(), 1);
+    assert_eq!(MerkleTree::new(&data[..3], 0).chunk_size(), 1);
+    assert_eq!(MerkleTree::from_reader(&data[..3], 0)?.chunk_size(), 1);
+
+    // the proof of the promoted chunk 4 of 5 also fits position 1 of 2
+    let tree = MerkleTree::new(&data[..5 * 1024], 1024);
+    let proof = tree.proof(4).unwrap();
+    let chunk = &data[4 * 1024..5 * 1024];
+    assert!(proof.verify(&tree.root(), 4, 5, chunk));
+    let relabelled = Proof {
+        index: 1,
+        leaf_count: 2,
+        ..proof
+    };
+    assert_eq!(relabelled.root(chunk)?, tree.root());
+    assert!(!relabelled.verify(&tree.root(), 1, 5, chunk));
+    assert!(!relabelled.verify(&tree.root(), 4, 5, chunk));
+    Ok(())
+}
@@ -30,7 +30,7 @@ fn test() {
     use rand::SeedableRng;
     use tracing::info;
 
-    crate::log::init_log_filter("info");
+    let _log = crate::log::scoped_log(&Default::default());
 
     let mut rng = ChaCha8Rng::seed_from_u64(0);
     let probability = vec![1, 1, 1, 1, 1];
@@ -2,6 +2,8 @@ pub use axum;
 
 use std::{future::Future, net::SocketAddr, path::PathBuf, time::Duration};
 
+#[cfg(feature = "log")]
+use axum::extract::State;
 use axum::{
     BoxError, Router,
     handler::HandlerWithoutStateExt,
@@ -16,6 +18,8 @@ use serde_json::json;
 use tokio::{net::TcpListener, signal};
 use tracing::info;
 
+#[cfg(feature = "log")]
+use crate::log::{LogConfig, LogHandle, LogQuery, LogReader};
 use crate::signal::waiting_for_shutdown;
 
 #[derive(Clone, Copy)]
@@ -60,8 +64,162 @@ async fn health() -> Result<impl IntoResponse, RESTfulError> {
     ok_simple()
 }
 
+/// `Authorization: Bearer {token}` matches `token`, compared in constant time.
+#[cfg(feature = "log")]
+fn authorized(headers: &axum::http::HeaderMap, token: &str) -> bool {
+    let Some(bearer) = headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+    !token.is_empty()
+        && bearer.len() == token.len()
+        && bearer
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Admin routes to read (`GET`) and replace (`PUT`, directive as body) the log
+/// filter, for requests with `Authorization: Bearer {token}`.
+#[cfg(feature = "log")]
+pub fn log_filter_router(handle: LogHandle, token: String) -> Router {
+    use std::sync::Arc;
+
+    use axum::http::HeaderMap;
+
+    #[derive(Clone)]
+    struct LogFilterState {
+        handle: LogHandle,
+        token: Arc<str>,
+    }
+
+    async fn get_filter(
+        State(state): State<LogFilterState>,
+        headers: HeaderMap,
+    ) -> Result<impl IntoResponse, RESTfulError> {
+        if !authorized(&headers, &state.token) {
+            return Err(err(401, "unauthorized".to_owned()));
+        }
+        ok(state.handle.filter()?)
+    }
+
+    async fn put_filter(
+        State(state): State<LogFilterState>,
+        headers: HeaderMap,
+        body: String,
+    ) -> Result<impl IntoResponse, RESTfulError> {
+        if !authorized(&headers, &state.token) {
+            return Err(err(401, "unauthorized".to_owned()));
+        }
+        state
+            .handle
+            .set_filter(body.trim())
+            .map_err(|e| err(400, e.to_string()))?;
+        ok_simple()
+    }
+
+    Router::new()
+        .route("/log/filter", get(get_filter).put(put_filter))
+        .with_state(LogFilterState {
+            handle,
+            token: token.into(),
+        })
+}
+
+/// Streams the lines of rotated log files matching the `LogQuery` in the query
+/// string as plain text, for requests with `Authorization: Bearer {token}`.
+#[cfg(feature = "log")]
+pub fn log_query_router(config: LogConfig, token: String) -> Router {
+    use std::{convert::Infallible, sync::Arc};
+
+    use axum::{
+        body::Body,
+        extract::Query,
+        http::{HeaderMap, header::CONTENT_TYPE},
+    };
+
+    #[derive(Clone)]
+    struct LogQueryState {
+        config: Arc<LogConfig>,
+        token: Arc<str>,
+    }
+
+    async fn query_log(
+        State(state): State<LogQueryState>,
+        headers: HeaderMap,
+        Query(query): Query<LogQuery>,
+    ) -> Result<Response, RESTfulError> {
+        if !authorized(&headers, &state.token) {
+            return Err(err(401, "unauthorized".to_owned()));
+        }
+        query.level().map_err(|e| err(400, e.to_string()))?;
+
+        let (tx, rx) = tokio::sync::mpsc::channel(64);
+        tokio::task::spawn_blocking(move || {
+            let sent = LogReader::new(&state.config).and_then(|reader| {
+                reader.scan(&query, |entry| tx.blocking_send(entry.text + "\n").is_ok())
+            });
+            if let Err(e) = sent {
+                tracing::warn!("log query failed: {e}");
+            }
+        });
+        let lines = futures_util::stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|line| (Ok::<_, Infallible>(line), rx))
+        });
+        Ok((
+            [(CONTENT_TYPE, "text/plain; charset=utf-8")],
+            Body::from_stream(lines),
+        )
+            .into_response())
+    }
+
+    Router::new()
+        .route("/log/query", get(query_log))
+        .with_state(LogQueryState {
+            config: Arc::new(config),
+            token: token.into(),
+        })
+}
+
+/// Continues the trace of an incoming W3C `traceparent` header in a `request` span.
+#[cfg(feature = "otel")]
+async fn trace_context(request: axum::extract::Request, next: axum::middleware::Next) -> Response {
+    use opentelemetry::propagation::Extractor;
+    use tracing::Instrument;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    struct HeaderExtractor<'a>(&'a axum::http::HeaderMap);
+
+    impl Extractor for HeaderExtractor<'_> {
+        fn get(&self, key: &str) -> Option<&str> {
+            self.0.get(key).and_then(|v| v.to_str().ok())
+        }
+
+        fn keys(&self) -> Vec<&str> {
+            self.0.keys().map(|k| k.as_str()).collect()
+        }
+    }
+
+    let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(request.headers()))
+    });
+    let span = tracing::info_span!(
+        "request",
+        method = %request.method(),
+        path = %request.uri().path(),
+    );
+    span.set_parent(parent).ok();
+    next.run(request).instrument(span).await
+}
+
 pub async fn http_serve(port: u16, router: Router) -> Result<()> {
     let app = router.route("/health", get(health));
+    #[cfg(feature = "otel")]
+    let app = app.layer(axum::middleware::from_fn(trace_context));
 
     let listener = TcpListener::bind(format!("[::]:{}", port)).await?;
 
@@ -92,6 +250,8 @@ pub async fn https_serve(
         RustlsConfig::from_pem_file(PathBuf::from(cert_path), PathBuf::from(key_path)).await?;
 
     let app = router.route("/health", get(health));
+    #[cfg(feature = "otel")]
+    let app = app.layer(axum::middleware::from_fn(trace_context));
 
     let addr = SocketAddr::from(([0, 0, 0, 0], https_port));
     info!("listening on https {addr}");
@@ -221,3 +381,156 @@ where
         .await
         .unwrap();
 }
+
+#[cfg(feature = "log")]
+#[tokio::test]
+async fn test_log_filter() -> Result<()> {
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+
+    let (handle, _guard) = crate::log::scoped_log(&LogConfig::default())?;
+    let router = log_filter_router(handle.clone(), "secret".to_owned());
+    let put = |auth: Option<&str>, filter: &str| {
+        let request = Request::put("/log/filter");
+        match auth {
+            Some(auth) => request.header("authorization", auth),
+            None => request,
+        }
+        .body(Body::from(filter.to_owned()))
+        .unwrap()
+    };
+
+    let response = router.clone().oneshot(put(None, "trace")).await?;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = router
+        .clone()
+        .oneshot(put(Some("Bearer wrong!"), "trace"))
+        .await?;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(handle.filter()?, "info");
+
+    let response = router
+        .clone()
+        .oneshot(put(Some("Bearer secret"), "debug"))
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(handle.filter()?, "debug");
+    let response = router
+        .clone()
+        .oneshot(put(Some("Bearer secret"), "[["))
+        .await?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let get = Request::get("/log/filter").body(Body::empty())?;
+    assert_eq!(
+        router.oneshot(get).await?.status(),
+        StatusCode::UNAUTHORIZED
+    );
+    Ok(())
+}
+
+#[cfg(feature = "log")]
+#[tokio::test]
+async fn test_log_query() -> Result<()> {
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+
+    use crate::log::RollingFileConfig;
+
+    let directory = std::env::temp_dir().join("common_x_log_query_router_test");
+    std::fs::remove_dir_all(&directory).ok();
+    std::fs::create_dir_all(&directory)?;
+    std::fs::write(
+        directory.join("app.2026-01-01"),
+        "2026-01-01T10:00:00.000Z  INFO ThreadId(01) app::db: connected\n\
+         2026-01-01T10:00:01.000Z ERROR ThreadId(02) app::http: failed\n",
+    )?;
+    let router = log_query_router(
+        LogConfig {
+            rolling_file: Some(RollingFileConfig {
+                directory: directory.to_string_lossy().into_owned(),
+                prefix: "app".to_owned(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        "secret".to_owned(),
+    );
+    let get = |uri: &str, auth: bool| {
+        let mut request = Request::get(uri);
+        if auth {
+            request = request.header("authorization", "Bearer secret");
+        }
+        request.body(Body::empty()).unwrap()
+    };
+
+    let response = router.clone().oneshot(get("/log/query", false)).await?;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = router
+        .clone()
+        .oneshot(get("/log/query?level=loud", true))
+        .await?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // an unescaped `+` in the offset arrives as a space
+    let response = router
+        .oneshot(get(
+            "/log/query?level=error&since=2026-01-01T10:00:00.500+00:00",
+            true,
+        ))
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()["content-type"],
+        "text/plain; charset=utf-8"
+    );
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+    assert_eq!(
+        body,
+        "2026-01-01T10:00:01.000Z ERROR ThreadId(02) app::http: failed\n"
+    );
+    std::fs::remove_dir_all(&directory)?;
+    Ok(())
+}
+
+#[cfg(feature = "otel")]
+#[tokio::test]
+async fn test_trace_context() -> Result<()> {
+    use axum::{body::Body, http::Request};
+    use opentelemetry::trace::{TraceContextExt, TracerProvider};
+    use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::SdkTracerProvider};
+    use tower::ServiceExt;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    async fn trace_id() -> String {
+        let context = tracing::Span::current().context();
+        context.span().span_context().trace_id().to_string()
+    }
+
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+    let provider = SdkTracerProvider::builder().build();
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let router = Router::new()
+        .route("/", get(trace_id))
+        .layer(axum::middleware::from_fn(trace_context));
+    let request = Request::get("/")
+        .header(
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        )
+        .body(Body::empty())?;
+    let response = router.clone().oneshot(request).await?;
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+    assert_eq!(body, "4bf92f3577b34da6a3ce929d0e0e4736");
+
+    let response = router
+        .oneshot(Request::get("/").body(Body::empty())?)
+        .await?;
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+    assert_ne!(body, "4bf92f3577b34da6a3ce929d0e0e4736");
+    Ok(())
+}
@@ -0,0 +1,311 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use color_eyre::{Result, eyre::eyre};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use crate::graceful_shutdown::{CloseSignal, CloseToken};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SupervisorConfig {
+    /// restarts allowed inside `max_window` before escalating to a full shutdown
+    pub max_restarts: usize,
+    /// seconds
+    pub max_window: u64,
+    /// milliseconds
+    pub backoff_min: u64,
+    /// milliseconds
+    pub backoff_max: u64,
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        Self {
+            max_restarts: 5,
+            max_window: 60,
+            backoff_min: 100,
+            backoff_max: 30_000,
+        }
+    }
+}
+
+/// When a child is restarted, applied one-for-one to each child.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Restart {
+    /// always restarted
+    Permanent,
+    /// restarted only when it fails
+    Transient,
+    /// never restarted
+    Temporary,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChildStatus {
+    Running,
+    Backoff,
+    Stopped,
+    Failed,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ChildState {
+    pub name: String,
+    pub restart: Restart,
+    pub status: ChildStatus,
+    pub restarts: usize,
+    pub last_error: Option<String>,
+}
+
+type Children = Arc<Mutex<HashMap<String, ChildState>>>;
+
+/// Cheap to clone, e.g. for a status endpoint: clones share the children and token.
+#[derive(Clone)]
+pub struct Supervisor {
+    config: SupervisorConfig,
+    token: Arc<CloseToken>,
+    children: Children,
+}
+
+impl Supervisor {
+    pub fn new(config: SupervisorConfig, token: CloseToken) -> Self {
+        Self {
+            config,
+            token: Arc::new(token),
+            children: Default::default(),
+        }
+    }
+
+    /// Each child gets its own `child_token` of the supervisor's token. The returned
+    /// handle resolves once the child is no longer supervised, i.e. it stopped,
+    /// gave up or was shut down.
+    pub fn spawn<F, Fut>(&self, name: &str, restart: Restart, f: F) -> Result<JoinHandle<()>>
+    where
+        F: Fn(CloseSignal) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        {
+            let mut children = self.children.lock().unwrap();
+            if children.contains_key(name) {
+                return Err(eyre!("child({name}) already exists"));
+            }
+            children.insert(
+                name.to_owned(),
+                ChildState {
+                    name: name.to_owned(),
+                    restart,
+                    status: ChildStatus::Running,
+                    restarts: 0,
+                    last_error: None,
+                },
+            );
+        }
+
+        let child = Child {
+            name: name.to_owned(),
+            restart,
+            config: self.config.clone(),
+            children: self.children.clone(),
+        };
+        let token = self.token.child_token();
+        Ok(tokio::spawn(async move {
+            child.supervise(f, &token).await;
+            // dropping a `CloseToken` blocks until it is closed, which a stopped
+            // child must not make its handle wait for
+            tokio::spawn(async move {
+                token.signal().closed_async().await;
+                drop(token);
+            });
+        }))
+    }
+
+    pub fn status(&self, name: &str) -> Option<ChildState> {
+        self.children.lock().unwrap().get(name).cloned()
+    }
+
+    pub fn statuses(&self) -> Vec<ChildState> {
+        let mut states: Vec<_> = self.children.lock().unwrap().values().cloned().collect();
+        states.sort_by(|a, b| a.name.cmp(&b.name));
+        states
+    }
+
+    /// `false` once any child is waiting to restart or has given up.
+    pub fn is_healthy(&self) -> bool {
+        self.children
+            .lock()
+            .unwrap()
+            .values()
+            .all(|c| matches!(c.status, ChildStatus::Running | ChildStatus::Stopped))
+    }
+}
+
+struct Child {
+    name: String,
+    restart: Restart,
+    config: SupervisorConfig,
+    children: Children,
+}
+
+impl Child {
+    fn update(&self, f: impl FnOnce(&mut ChildState)) {
+        if let Some(state) = self.children.lock().unwrap().get_mut(&self.name) {
+            f(state);
+        }
+    }
+
+    fn backoff(&self, failures: usize) -> Duration {
+        let shift = failures.saturating_sub(1).min(31) as u32;
+        let delay = self.config.backoff_min.saturating_mul(1 << shift);
+        Duration::from_millis(delay.min(self.config.backoff_max))
+    }
+
+    async fn supervise<F, Fut>(&self, f: F, token: &CloseToken)
+    where
+        F: Fn(CloseSignal) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let (name, restart) = (&self.name, self.restart);
+        let window = Duration::from_secs(self.config.max_window);
+        let mut failures: VecDeque<Instant> = VecDeque::new();
+        loop {
+            self.update(|s| s.status = ChildStatus::Running);
+            let result = match tokio::spawn(f(token.signal())).await {
+                Ok(result) => result,
+                Err(e) => Err(eyre!("child panicked: {e}")),
+            };
+            if token.is_closed() {
+                break;
+            }
+
+            let failed = match result {
+                Ok(()) => {
+                    info!("child({name}) exited");
+                    if restart != Restart::Permanent {
+                        self.update(|s| s.status = ChildStatus::Stopped);
+                        break;
+                    }
+                    false
+                }
+                Err(e) => {
+                    warn!("child({name}) failed: {e}");
+                    self.update(|s| s.last_error = Some(e.to_string()));
+                    if restart == Restart::Temporary {
+                        self.update(|s| s.status = ChildStatus::Failed);
+                        break;
+                    }
+                    true
+                }
+            };
+
+            let now = Instant::now();
+            if failed {
+                failures.push_back(now);
+            }
+            while failures
+                .front()
+                .is_some_and(|t| now.duration_since(*t) > window)
+            {
+                failures.pop_front();
+            }
+            if failures.len() > self.config.max_restarts {
+                error!(
+                    "child({name}) failed {} times in {}s, shutting down",
+                    failures.len(),
+                    self.config.max_window
+                );
+                self.update(|s| s.status = ChildStatus::Failed);
+                token.close();
+                break;
+            }
+
+            self.update(|s| s.status = ChildStatus::Backoff);
+            let signal = token.signal();
+            tokio::select! {
+                _ = tokio::time::sleep(self.backoff(failures.len())) => {}
+                _ = signal.closed_async() => break,
+            }
+            self.update(|s| s.restarts += 1);
+            info!("child({name}) restarting");
+        }
+        if token.is_closed() {
+            self.update(|s| {
+                if s.status != ChildStatus::Failed {
+                    s.status = ChildStatus::Stopped
+                }
+            });
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test() -> Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let token = CloseToken::default();
+    let supervisor = Supervisor::new(
+        SupervisorConfig {
+            max_restarts: 3,
+            backoff_min: 1,
+            backoff_max: 10,
+            ..Default::default()
+        },
+        token.clone(),
+    );
+
+    let runs = Arc::new(AtomicUsize::new(0));
+    let runs_clone = runs.clone();
+    let poller = supervisor.spawn("poller", Restart::Permanent, move |signal| {
+        let run = runs_clone.fetch_add(1, Ordering::SeqCst);
+        async move {
+            if run < 2 {
+                return Err(eyre!("poll failed"));
+            }
+            signal.closed_async().await;
+            Ok(())
+        }
+    })?;
+    while runs.load(Ordering::SeqCst) < 3 {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    let state = supervisor.status("poller").unwrap();
+    assert_eq!(state.restarts, 2);
+    assert_eq!(state.status, ChildStatus::Running);
+    assert!(supervisor.is_healthy());
+
+    // a clone for a status endpoint must not block when dropped
+    let clone = supervisor.clone();
+    tokio::time::timeout(
+        Duration::from_secs(1),
+        tokio::task::spawn_blocking(move || drop(clone)),
+    )
+    .await??;
+    let once = supervisor.spawn("once", Restart::Transient, |_| async { Ok(()) })?;
+    tokio::time::timeout(Duration::from_secs(1), once).await??;
+    assert_eq!(
+        supervisor.status("once").unwrap().status,
+        ChildStatus::Stopped
+    );
+
+    let consumer = supervisor.spawn("consumer", Restart::Transient, |_| async {
+        Err(eyre!("consume failed"))
+    })?;
+    token.closed_async().await;
+    poller.await?;
+    consumer.await?;
+    assert_eq!(
+        supervisor.status("consumer").unwrap().status,
+        ChildStatus::Failed
+    );
+    assert_eq!(
+        supervisor.status("poller").unwrap().status,
+        ChildStatus::Stopped
+    );
+    Ok(())
+}
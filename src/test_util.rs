@@ -0,0 +1,46 @@
+#[cfg(feature = "log")]
+use std::sync::{Arc, Mutex};
+
+/// Shared in-memory writer, e.g. `with_writer(move || buffer.clone())`.
+#[cfg(feature = "log")]
+#[derive(Clone, Default)]
+pub(crate) struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+#[cfg(feature = "log")]
+impl Buffer {
+    pub(crate) fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+#[cfg(feature = "log")]
+impl std::io::Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Stand-in server on a free local port, calling `handle` with a reader and a
+/// writer for each connection.
+#[cfg(any(feature = "otel", feature = "mailer"))]
+pub(crate) fn serve<F>(handle: F) -> std::io::Result<u16>
+where
+    F: Fn(&mut std::io::BufReader<std::net::TcpStream>, &mut std::net::TcpStream) + Send + 'static,
+{
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let Ok(reader) = stream.try_clone() else {
+                continue;
+            };
+            handle(&mut std::io::BufReader::new(reader), &mut stream);
+        }
+    });
+    Ok(port)
+}